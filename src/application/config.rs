@@ -2,7 +2,7 @@ use glib::subclass::types::ObjectSubclassIsExt;
 use gtk4::gdk::Event;
 
 use crate::{
-    config::{GlobalConfig, TerminalConfig},
+    config::{GlobalConfig, TerminalConfig, TmuxCloseAction},
     keyboard::{check_keybinding_match, Keybinding, KeyboardAction},
 };
 
@@ -24,6 +24,28 @@ impl IvyApplication {
         keybindings.clone()
     }
 
+    pub fn get_saved_layout(&self) -> Option<String> {
+        let config = self.imp().config.borrow();
+        config.tmux.saved_layout.clone()
+    }
+
+    pub fn save_layout(&self, layout: String) {
+        let mut config = self.imp().config.borrow_mut();
+        config.tmux.saved_layout = Some(layout);
+        config.write_config_to_file();
+    }
+
+    pub fn get_tmux_close_action(&self) -> TmuxCloseAction {
+        let config = self.imp().config.borrow();
+        config.tmux.close_action
+    }
+
+    pub fn set_tmux_close_action(&self, close_action: TmuxCloseAction) {
+        let mut config = self.imp().config.borrow_mut();
+        config.tmux.close_action = close_action;
+        config.write_config_to_file();
+    }
+
     pub fn update_config(&self, new: GlobalConfig, keybindings: Vec<Keybinding>) {
         // Write config to file
         new.write_config_to_file();
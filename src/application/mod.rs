@@ -11,6 +11,7 @@ use log::debug;
 use crate::helpers::borrow_clone;
 use crate::normal_widgets::IvyNormalWindow;
 use crate::settings_window::spawn_preferences_window;
+use crate::tmux_api::AttachMode;
 use crate::tmux_widgets::IvyTmuxWindow;
 
 const APPLICATION_ID: &str = "com.tomiyou.ivyTerm";
@@ -60,8 +61,13 @@ impl IvyApplication {
         window.present();
     }
 
-    pub fn new_tmux_window(&self, tmux_session: &str, ssh_target: Option<(&str, &str)>) {
-        let window = IvyTmuxWindow::new(self, tmux_session, ssh_target);
+    pub fn new_tmux_window(
+        &self,
+        tmux_session: &str,
+        attach_mode: AttachMode,
+        ssh_target: Option<&str>,
+    ) {
+        let window = IvyTmuxWindow::new(self, tmux_session, attach_mode, ssh_target);
         window.present();
     }
 
@@ -3,6 +3,7 @@ use std::{fs, io::Write, path::PathBuf};
 use gtk4::{gdk::RGBA, pango::FontDescription};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 pub use terminal::{ColorScheme, TerminalConfig};
+pub use tmux::TmuxCloseAction;
 use tmux::TmuxConfig;
 
 use crate::keyboard::Keybindings;
@@ -115,9 +116,9 @@ impl From<RGBA> for IvyColor {
     }
 }
 
-impl Into<RGBA> for IvyColor {
-    fn into(self) -> RGBA {
-        self.0
+impl From<IvyColor> for RGBA {
+    fn from(val: IvyColor) -> Self {
+        val.0
     }
 }
 
@@ -164,9 +165,9 @@ impl From<FontDescription> for IvyFont {
     }
 }
 
-impl Into<FontDescription> for IvyFont {
-    fn into(self) -> FontDescription {
-        self.0
+impl From<IvyFont> for FontDescription {
+    fn from(val: IvyFont) -> Self {
+        val.0
     }
 }
 
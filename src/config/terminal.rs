@@ -58,7 +58,7 @@ impl ColorScheme {
     }
 
     pub fn get(&self) -> Vec<&RGBA> {
-        self.colors.iter().map(|c| c).collect()
+        self.colors.iter().collect()
     }
 }
 
@@ -3,16 +3,33 @@ use serde::{Deserialize, Serialize};
 
 use super::IvyColor;
 
+/// What happens to the Tmux session when its window is closed
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TmuxCloseAction {
+    #[default]
+    Ask,
+    Detach,
+    Kill,
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 pub struct TmuxConfig {
     #[serde(default = "default_window_color")]
     pub window_color: IvyColor,
+    /// Layout remembered with the TabLayoutSave keybinding
+    #[serde(default)]
+    pub saved_layout: Option<String>,
+    #[serde(default)]
+    pub close_action: TmuxCloseAction,
 }
 
 impl Default for TmuxConfig {
     fn default() -> Self {
         Self {
             window_color: default_window_color(),
+            saved_layout: None,
+            close_action: TmuxCloseAction::default(),
         }
     }
 }
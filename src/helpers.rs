@@ -9,13 +9,33 @@ use const_format::concatcp;
 pub enum IvyError {
     #[error("executing remote Tmux command failed")]
     TmuxSpawnFailed = 0,
+    #[error("connecting to SSH host failed")]
+    SshFailed,
 }
 
+#[derive(Debug)]
 pub enum TmuxError {
     EventChannelClosed,
     ExitEventReceived,
     SshClosed,
     ErrorParsingUTF8,
+    ErrorParsingBlockGuard,
+    MalformedOutput,
+}
+
+impl TmuxError {
+    /// Malformed data only affects a single line, while other errors mean we
+    /// have to stop parsing Tmux output
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            TmuxError::EventChannelClosed | TmuxError::ExitEventReceived | TmuxError::SshClosed => {
+                true
+            }
+            TmuxError::ErrorParsingUTF8
+            | TmuxError::ErrorParsingBlockGuard
+            | TmuxError::MalformedOutput => false,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -49,7 +69,7 @@ impl<T> Default for SortedVec<T> {
 impl<T: Eq + Clone> SortedVec<T> {
     pub fn insert(&mut self, id: u32, terminal: &T) -> usize {
         let terminal = WithId {
-            id: id,
+            id,
             terminal: terminal.clone(),
         };
 
@@ -62,7 +82,7 @@ impl<T: Eq + Clone> SortedVec<T> {
 
     pub fn push(&mut self, id: u32, terminal: &T) -> usize {
         let sorted_terminal = WithId {
-            id: id,
+            id,
             terminal: terminal.clone(),
         };
 
@@ -150,11 +170,8 @@ pub fn open_editor(path: &str, ssh_target: &Option<String>) {
     }
 
     // Spawn editor
-    match command.spawn() {
-        Err(err) => {
-            eprintln!("Error opening editor: {}", err);
-        }
-        _ => {}
+    if let Err(err) = command.spawn() {
+        eprintln!("Error opening editor: {}", err);
     }
 }
 
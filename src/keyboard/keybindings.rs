@@ -5,7 +5,7 @@ use gtk4::{
 use libadwaita::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use super::{Direction, KeyboardAction};
+use super::{Direction, KeyboardAction, LayoutPreset};
 
 #[derive(Clone)]
 pub struct Keybinding {
@@ -18,8 +18,8 @@ impl Keybinding {
     fn new(trigger: &str, action: KeyboardAction, description: &'static str) -> Self {
         Self {
             trigger: ShortcutTrigger::parse_string(trigger),
-            action: action,
-            description: description,
+            action,
+            description,
         }
     }
 }
@@ -48,6 +48,42 @@ pub struct Keybindings {
     move_up: String,
     #[serde(default = "default_move_down")]
     move_down: String,
+    #[serde(default = "default_swap_right")]
+    swap_right: String,
+    #[serde(default = "default_swap_left")]
+    swap_left: String,
+    #[serde(default = "default_swap_up")]
+    swap_up: String,
+    #[serde(default = "default_swap_down")]
+    swap_down: String,
+    #[serde(default = "default_break_pane")]
+    break_pane: String,
+    #[serde(default = "default_join_pane")]
+    join_pane: String,
+    #[serde(default = "default_rotate_tab")]
+    rotate_tab: String,
+    #[serde(default = "default_layout_even_horizontal")]
+    layout_even_horizontal: String,
+    #[serde(default = "default_layout_even_vertical")]
+    layout_even_vertical: String,
+    #[serde(default = "default_layout_main_horizontal")]
+    layout_main_horizontal: String,
+    #[serde(default = "default_layout_main_vertical")]
+    layout_main_vertical: String,
+    #[serde(default = "default_layout_tiled")]
+    layout_tiled: String,
+    #[serde(default = "default_next_layout")]
+    next_layout: String,
+    #[serde(default = "default_save_layout")]
+    save_layout: String,
+    #[serde(default = "default_restore_layout")]
+    restore_layout: String,
+    #[serde(default = "default_synchronize_panes")]
+    synchronize_panes: String,
+    #[serde(default = "default_detach_session")]
+    detach_session: String,
+    #[serde(default = "default_kill_session")]
+    kill_session: String,
     #[serde(default = "default_rename_tab")]
     rename_tab: String,
     #[serde(default = "default_paste_clipboard")]
@@ -60,88 +96,170 @@ pub struct Keybindings {
 
 impl Keybindings {
     pub fn init(&mut self) -> Vec<Keybinding> {
-        let mut keybindings = Vec::new();
-
         // Put the most common keybindings first (for optimization)
-        keybindings.push(Keybinding::new(
-            &self.move_right,
-            KeyboardAction::MoveFocus(Direction::Right),
-            "Move focus to the Terminal on the right",
-        ));
-        keybindings.push(Keybinding::new(
-            &self.move_left,
-            KeyboardAction::MoveFocus(Direction::Left),
-            "Move focus to the Terminal on the left",
-        ));
-        keybindings.push(Keybinding::new(
-            &self.move_up,
-            KeyboardAction::MoveFocus(Direction::Up),
-            "Move focus to the Terminal on the up",
-        ));
-        keybindings.push(Keybinding::new(
-            &self.move_down,
-            KeyboardAction::MoveFocus(Direction::Down),
-            "Move focus to the Terminal on the down",
-        ));
-
-        keybindings.push(Keybinding::new(
-            &self.toggle_zoom,
-            KeyboardAction::ToggleZoom,
-            "Toggle zoom for the current Terminal",
-        ));
-        keybindings.push(Keybinding::new(
-            &self.copy_selection,
-            KeyboardAction::CopySelected,
-            "Copy selected text on the current Terminal",
-        ));
-
-        keybindings.push(Keybinding::new(
-            &self.new_tab,
-            KeyboardAction::TabNew,
-            "Open a new Tab",
-        ));
-        keybindings.push(Keybinding::new(
-            &self.close_tab,
-            KeyboardAction::TabClose,
-            "Close the current Tab",
-        ));
-        keybindings.push(Keybinding::new(
-            &self.split_horizontal,
-            KeyboardAction::PaneSplit(true),
-            "Split the current Tab horizontally",
-        ));
-        keybindings.push(Keybinding::new(
-            &self.split_vertical,
-            KeyboardAction::PaneSplit(false),
-            "Split the current Tab vertically",
-        ));
-        keybindings.push(Keybinding::new(
-            &self.close_pane,
-            KeyboardAction::PaneClose,
-            "Close the current Terminal",
-        ));
-        keybindings.push(Keybinding::new(
-            &self.rename_tab,
-            KeyboardAction::TabRename,
-            "Rename the current Tab",
-        ));
-        keybindings.push(Keybinding::new(
-            &self.paste_clipboard,
-            KeyboardAction::PasteClipboard,
-            "Paste to Terminal",
-        ));
-        keybindings.push(Keybinding::new(
-            &self.open_editor_cwd,
-            KeyboardAction::OpenEditorCwd,
-            "Open current working directory in VS Code",
-        ));
-        keybindings.push(Keybinding::new(
-            &self.clear_scrollback,
-            KeyboardAction::ClearScrollback,
-            "Clear Tmux scrollback",
-        ));
-
-        keybindings
+        vec![
+            Keybinding::new(
+                &self.move_right,
+                KeyboardAction::MoveFocus(Direction::Right),
+                "Move focus to the Terminal on the right",
+            ),
+            Keybinding::new(
+                &self.move_left,
+                KeyboardAction::MoveFocus(Direction::Left),
+                "Move focus to the Terminal on the left",
+            ),
+            Keybinding::new(
+                &self.move_up,
+                KeyboardAction::MoveFocus(Direction::Up),
+                "Move focus to the Terminal on the up",
+            ),
+            Keybinding::new(
+                &self.move_down,
+                KeyboardAction::MoveFocus(Direction::Down),
+                "Move focus to the Terminal on the down",
+            ),
+            Keybinding::new(
+                &self.toggle_zoom,
+                KeyboardAction::ToggleZoom,
+                "Toggle zoom for the current Terminal",
+            ),
+            Keybinding::new(
+                &self.copy_selection,
+                KeyboardAction::CopySelected,
+                "Copy selected text on the current Terminal",
+            ),
+            Keybinding::new(&self.new_tab, KeyboardAction::TabNew, "Open a new Tab"),
+            Keybinding::new(
+                &self.close_tab,
+                KeyboardAction::TabClose,
+                "Close the current Tab",
+            ),
+            Keybinding::new(
+                &self.split_horizontal,
+                KeyboardAction::PaneSplit(true),
+                "Split the current Tab horizontally",
+            ),
+            Keybinding::new(
+                &self.split_vertical,
+                KeyboardAction::PaneSplit(false),
+                "Split the current Tab vertically",
+            ),
+            Keybinding::new(
+                &self.close_pane,
+                KeyboardAction::PaneClose,
+                "Close the current Terminal",
+            ),
+            Keybinding::new(
+                &self.swap_right,
+                KeyboardAction::PaneSwap(Direction::Right),
+                "Swap the current Terminal with the one on the right (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.swap_left,
+                KeyboardAction::PaneSwap(Direction::Left),
+                "Swap the current Terminal with the one on the left (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.swap_up,
+                KeyboardAction::PaneSwap(Direction::Up),
+                "Swap the current Terminal with the one above (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.swap_down,
+                KeyboardAction::PaneSwap(Direction::Down),
+                "Swap the current Terminal with the one below (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.break_pane,
+                KeyboardAction::PaneBreak,
+                "Move the current Terminal to a new Tab (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.join_pane,
+                KeyboardAction::PaneJoin,
+                "Move the current Terminal to the previous Tab (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.rotate_tab,
+                KeyboardAction::TabRotate,
+                "Rotate Terminals of the current Tab (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.layout_even_horizontal,
+                KeyboardAction::TabLayout(LayoutPreset::EvenHorizontal),
+                "Spread Terminals of the current Tab evenly from left to right (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.layout_even_vertical,
+                KeyboardAction::TabLayout(LayoutPreset::EvenVertical),
+                "Spread Terminals of the current Tab evenly from top to bottom (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.layout_main_horizontal,
+                KeyboardAction::TabLayout(LayoutPreset::MainHorizontal),
+                "Show the current Terminal on top, with the others below it (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.layout_main_vertical,
+                KeyboardAction::TabLayout(LayoutPreset::MainVertical),
+                "Show the current Terminal on the left, with the others next to it (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.layout_tiled,
+                KeyboardAction::TabLayout(LayoutPreset::Tiled),
+                "Spread Terminals of the current Tab evenly in rows and columns (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.next_layout,
+                KeyboardAction::TabNextLayout,
+                "Switch the current Tab to the next layout preset (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.save_layout,
+                KeyboardAction::TabLayoutSave,
+                "Remember the layout of the current Tab (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.restore_layout,
+                KeyboardAction::TabLayoutRestore,
+                "Apply the remembered layout to the current Tab (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.synchronize_panes,
+                KeyboardAction::TabSynchronize,
+                "Toggle sending input to all Terminals of the current Tab (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.detach_session,
+                KeyboardAction::SessionDetach,
+                "Detach from the Tmux session, leaving it running (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.kill_session,
+                KeyboardAction::SessionKill,
+                "Kill the Tmux session, including all programs within it (Tmux only)",
+            ),
+            Keybinding::new(
+                &self.rename_tab,
+                KeyboardAction::TabRename,
+                "Rename the current Tab",
+            ),
+            Keybinding::new(
+                &self.paste_clipboard,
+                KeyboardAction::PasteClipboard,
+                "Paste to Terminal",
+            ),
+            Keybinding::new(
+                &self.open_editor_cwd,
+                KeyboardAction::OpenEditorCwd,
+                "Open current working directory in VS Code",
+            ),
+            Keybinding::new(
+                &self.clear_scrollback,
+                KeyboardAction::ClearScrollback,
+                "Clear Tmux scrollback",
+            ),
+        ]
     }
 
     pub fn update(&mut self, keybindings: &Vec<Keybinding>) {
@@ -158,6 +276,32 @@ impl Keybindings {
                 KeyboardAction::MoveFocus(Direction::Left) => self.move_left = trigger,
                 KeyboardAction::MoveFocus(Direction::Up) => self.move_up = trigger,
                 KeyboardAction::MoveFocus(Direction::Down) => self.move_down = trigger,
+                KeyboardAction::PaneSwap(Direction::Right) => self.swap_right = trigger,
+                KeyboardAction::PaneSwap(Direction::Left) => self.swap_left = trigger,
+                KeyboardAction::PaneSwap(Direction::Up) => self.swap_up = trigger,
+                KeyboardAction::PaneSwap(Direction::Down) => self.swap_down = trigger,
+                KeyboardAction::PaneBreak => self.break_pane = trigger,
+                KeyboardAction::PaneJoin => self.join_pane = trigger,
+                KeyboardAction::TabRotate => self.rotate_tab = trigger,
+                KeyboardAction::TabLayout(LayoutPreset::EvenHorizontal) => {
+                    self.layout_even_horizontal = trigger
+                }
+                KeyboardAction::TabLayout(LayoutPreset::EvenVertical) => {
+                    self.layout_even_vertical = trigger
+                }
+                KeyboardAction::TabLayout(LayoutPreset::MainHorizontal) => {
+                    self.layout_main_horizontal = trigger
+                }
+                KeyboardAction::TabLayout(LayoutPreset::MainVertical) => {
+                    self.layout_main_vertical = trigger
+                }
+                KeyboardAction::TabLayout(LayoutPreset::Tiled) => self.layout_tiled = trigger,
+                KeyboardAction::TabNextLayout => self.next_layout = trigger,
+                KeyboardAction::TabLayoutSave => self.save_layout = trigger,
+                KeyboardAction::TabLayoutRestore => self.restore_layout = trigger,
+                KeyboardAction::TabSynchronize => self.synchronize_panes = trigger,
+                KeyboardAction::SessionDetach => self.detach_session = trigger,
+                KeyboardAction::SessionKill => self.kill_session = trigger,
                 KeyboardAction::ToggleZoom => self.toggle_zoom = trigger,
                 KeyboardAction::CopySelected => self.copy_selection = trigger,
                 KeyboardAction::TabNew => self.new_tab = trigger,
@@ -190,7 +334,7 @@ pub fn check_keybinding_match(
     for keybinding in keybindings {
         if let Some(trigger) = &keybinding.trigger {
             if trigger.trigger(&event, true) == KeyMatch::Exact {
-                return Some(keybinding.action.clone());
+                return Some(keybinding.action);
             };
         }
     }
@@ -213,6 +357,24 @@ impl Default for Keybindings {
             move_left: default_move_left(),
             move_up: default_move_up(),
             move_down: default_move_down(),
+            swap_right: default_swap_right(),
+            swap_left: default_swap_left(),
+            swap_up: default_swap_up(),
+            swap_down: default_swap_down(),
+            break_pane: default_break_pane(),
+            join_pane: default_join_pane(),
+            rotate_tab: default_rotate_tab(),
+            layout_even_horizontal: default_layout_even_horizontal(),
+            layout_even_vertical: default_layout_even_vertical(),
+            layout_main_horizontal: default_layout_main_horizontal(),
+            layout_main_vertical: default_layout_main_vertical(),
+            layout_tiled: default_layout_tiled(),
+            next_layout: default_next_layout(),
+            save_layout: default_save_layout(),
+            restore_layout: default_restore_layout(),
+            synchronize_panes: default_synchronize_panes(),
+            detach_session: default_detach_session(),
+            kill_session: default_kill_session(),
             rename_tab: default_rename_tab(),
             paste_clipboard: default_paste_clipboard(),
             open_editor_cwd: default_open_editor_cwd(),
@@ -257,6 +419,61 @@ fn default_move_up() -> String {
 fn default_move_down() -> String {
     "<Alt>Down".to_string()
 }
+fn default_swap_right() -> String {
+    "<Alt><Shift>Right".to_string()
+}
+fn default_swap_left() -> String {
+    "<Alt><Shift>Left".to_string()
+}
+fn default_swap_up() -> String {
+    "<Alt><Shift>Up".to_string()
+}
+fn default_swap_down() -> String {
+    "<Alt><Shift>Down".to_string()
+}
+fn default_break_pane() -> String {
+    "<Ctrl><Shift>b".to_string()
+}
+fn default_join_pane() -> String {
+    "<Ctrl><Shift>j".to_string()
+}
+fn default_rotate_tab() -> String {
+    "<Ctrl><Shift>r".to_string()
+}
+fn default_layout_even_horizontal() -> String {
+    "<Ctrl><Alt>1".to_string()
+}
+fn default_layout_even_vertical() -> String {
+    "<Ctrl><Alt>2".to_string()
+}
+fn default_layout_main_horizontal() -> String {
+    "<Ctrl><Alt>3".to_string()
+}
+fn default_layout_main_vertical() -> String {
+    "<Ctrl><Alt>4".to_string()
+}
+fn default_layout_tiled() -> String {
+    "<Ctrl><Alt>5".to_string()
+}
+fn default_next_layout() -> String {
+    "<Ctrl><Alt>space".to_string()
+}
+fn default_save_layout() -> String {
+    "<Ctrl><Alt>s".to_string()
+}
+fn default_restore_layout() -> String {
+    "<Ctrl><Alt>r".to_string()
+}
+fn default_synchronize_panes() -> String {
+    "<Ctrl><Shift>s".to_string()
+}
+fn default_detach_session() -> String {
+    "<Ctrl><Shift>q".to_string()
+}
+fn default_kill_session() -> String {
+    // Unbound, since it is easy to lose work by accident
+    String::new()
+}
 fn default_rename_tab() -> String {
     "<Ctrl><Alt>A".to_string()
 }
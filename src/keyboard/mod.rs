@@ -1,6 +1,8 @@
 mod keybindings;
+mod tmux_keys;
 
 pub use keybindings::{check_keybinding_match, Keybinding, Keybindings};
+pub use tmux_keys::{keyval_to_tmux_key, TmuxKey};
 
 #[derive(Clone, PartialEq, Debug, Copy)]
 pub enum Direction {
@@ -10,6 +12,16 @@ pub enum Direction {
     Down,
 }
 
+/// Tmux `select-layout` presets
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum LayoutPreset {
+    EvenHorizontal,
+    EvenVertical,
+    MainHorizontal,
+    MainVertical,
+    Tiled,
+}
+
 #[derive(Clone, Debug, PartialEq, Copy)]
 pub enum KeyboardAction {
     TabNew,
@@ -19,19 +31,20 @@ pub enum KeyboardAction {
     PaneClose,
     // TODO: Correct naming
     MoveFocus(Direction),
+    PaneSwap(Direction),
+    PaneBreak,
+    PaneJoin,
+    TabRotate,
+    TabLayout(LayoutPreset),
+    TabNextLayout,
+    TabLayoutSave,
+    TabLayoutRestore,
+    TabSynchronize,
+    SessionDetach,
+    SessionKill,
     ToggleZoom,
     CopySelected,
     PasteClipboard,
     OpenEditorCwd,
     ClearScrollback,
 }
-
-pub fn keycode_to_arrow_key(keycode: u32) -> Option<Direction> {
-    match keycode {
-        111 => Some(Direction::Up),
-        113 => Some(Direction::Left),
-        114 => Some(Direction::Right),
-        116 => Some(Direction::Down),
-        _ => None,
-    }
-}
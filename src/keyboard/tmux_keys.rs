@@ -0,0 +1,167 @@
+use gtk4::gdk::{Key, ModifierType};
+
+/// Key as understood by Tmux `send-keys`
+#[derive(Debug, Clone, PartialEq)]
+pub enum TmuxKey {
+    /// Printable character, sent literally
+    Text(char),
+    /// Key name including modifier prefixes, e.g. "C-M-Up"
+    Named(String),
+}
+
+/// Tmux names of keys which do not produce text (or whose text Tmux would not
+/// understand), checked before the key's unicode character
+const KEY_NAMES: &[(Key, &str)] = &[
+    // Navigation
+    (Key::Up, "Up"),
+    (Key::Down, "Down"),
+    (Key::Left, "Left"),
+    (Key::Right, "Right"),
+    (Key::Home, "Home"),
+    (Key::End, "End"),
+    (Key::Page_Up, "PPage"),
+    (Key::Page_Down, "NPage"),
+    (Key::Insert, "IC"),
+    (Key::Delete, "DC"),
+    // Editing
+    (Key::BackSpace, "BSpace"),
+    (Key::Tab, "Tab"),
+    (Key::ISO_Left_Tab, "BTab"),
+    (Key::Return, "Enter"),
+    (Key::ISO_Enter, "Enter"),
+    (Key::Escape, "Escape"),
+    (Key::space, "Space"),
+    // Function keys
+    (Key::F1, "F1"),
+    (Key::F2, "F2"),
+    (Key::F3, "F3"),
+    (Key::F4, "F4"),
+    (Key::F5, "F5"),
+    (Key::F6, "F6"),
+    (Key::F7, "F7"),
+    (Key::F8, "F8"),
+    (Key::F9, "F9"),
+    (Key::F10, "F10"),
+    (Key::F11, "F11"),
+    (Key::F12, "F12"),
+    // Keypad with Num Lock on
+    (Key::KP_0, "KP0"),
+    (Key::KP_1, "KP1"),
+    (Key::KP_2, "KP2"),
+    (Key::KP_3, "KP3"),
+    (Key::KP_4, "KP4"),
+    (Key::KP_5, "KP5"),
+    (Key::KP_6, "KP6"),
+    (Key::KP_7, "KP7"),
+    (Key::KP_8, "KP8"),
+    (Key::KP_9, "KP9"),
+    (Key::KP_Decimal, "KP."),
+    (Key::KP_Divide, "KP/"),
+    (Key::KP_Multiply, "KP*"),
+    (Key::KP_Subtract, "KP-"),
+    (Key::KP_Add, "KP+"),
+    (Key::KP_Enter, "KPEnter"),
+    // Keypad with Num Lock off
+    (Key::KP_Up, "Up"),
+    (Key::KP_Down, "Down"),
+    (Key::KP_Left, "Left"),
+    (Key::KP_Right, "Right"),
+    (Key::KP_Home, "Home"),
+    (Key::KP_End, "End"),
+    (Key::KP_Page_Up, "PPage"),
+    (Key::KP_Page_Down, "NPage"),
+    (Key::KP_Insert, "IC"),
+    (Key::KP_Delete, "DC"),
+    (Key::KP_Begin, "KP5"),
+];
+
+/// Translates a GTK key press into a Tmux key. Returns None for keys Tmux can not
+/// send, such as modifiers on their own.
+///
+/// Shift is part of the character for printable keys (e.g. 'A' or '!'), so S- is
+/// only added to named keys, except for Shift+Tab which Tmux calls BTab.
+pub fn keyval_to_tmux_key(keyval: Key, state: ModifierType) -> Option<TmuxKey> {
+    let ctrl = state.contains(ModifierType::CONTROL_MASK);
+    let alt = state.contains(ModifierType::ALT_MASK);
+    let shift = state.contains(ModifierType::SHIFT_MASK);
+
+    let mut prefix = String::new();
+    if ctrl {
+        prefix.push_str("C-");
+    }
+    if alt {
+        prefix.push_str("M-");
+    }
+
+    if let Some((_, name)) = KEY_NAMES.iter().find(|(key, _)| *key == keyval) {
+        if shift && keyval != Key::ISO_Left_Tab {
+            prefix.push_str("S-");
+        }
+        return Some(TmuxKey::Named(format!("{}{}", prefix, name)));
+    }
+
+    let c = keyval.to_unicode()?;
+    if c.is_control() {
+        return None;
+    }
+
+    if prefix.is_empty() {
+        Some(TmuxKey::Text(c))
+    } else {
+        Some(TmuxKey::Named(format!("{}{}", prefix, c)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tmux_key_names() {
+        let ctrl = ModifierType::CONTROL_MASK;
+        let alt = ModifierType::ALT_MASK;
+        let shift = ModifierType::SHIFT_MASK;
+        let none = ModifierType::empty();
+
+        let named = |name: &str| Some(TmuxKey::Named(name.to_string()));
+        let cases = [
+            // Named keys, with modifier prefixes in the order Tmux prints them
+            (Key::Up, none, named("Up")),
+            (Key::Up, ctrl, named("C-Up")),
+            (Key::Up, alt, named("M-Up")),
+            (Key::Up, shift, named("S-Up")),
+            (Key::Up, ctrl | alt | shift, named("C-M-S-Up")),
+            (Key::F5, ctrl | shift, named("C-S-F5")),
+            (Key::Page_Down, none, named("NPage")),
+            (Key::KP_Page_Down, none, named("NPage")),
+            // Shift is part of the key name for Shift+Tab
+            (Key::ISO_Left_Tab, shift, named("BTab")),
+            (Key::ISO_Left_Tab, ctrl | shift, named("C-BTab")),
+            (Key::KP_Enter, none, named("KPEnter")),
+            (Key::Return, alt, named("M-Enter")),
+            // Printable keys, with Shift already applied to the character
+            (Key::a, none, Some(TmuxKey::Text('a'))),
+            (Key::A, shift, Some(TmuxKey::Text('A'))),
+            (Key::exclam, shift, Some(TmuxKey::Text('!'))),
+            (Key::a, ctrl, named("C-a")),
+            (Key::x, ctrl | alt, named("C-M-x")),
+            (Key::A, alt | shift, named("M-A")),
+            (Key::eacute, none, Some(TmuxKey::Text('é'))),
+            // Control characters and modifiers on their own
+            (Key::Linefeed, none, None),
+            (Key::Clear, ctrl, None),
+            (Key::Control_L, ctrl, None),
+            (Key::Shift_L, shift, None),
+        ];
+
+        for (keyval, state, expected) in cases {
+            assert_eq!(
+                keyval_to_tmux_key(keyval, state),
+                expected,
+                "{:?} with {:?}",
+                keyval.name(),
+                state
+            );
+        }
+    }
+}
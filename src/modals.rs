@@ -1,8 +1,15 @@
+use std::{cell::RefCell, rc::Rc};
+
 use glib::RustClosure;
-use gtk4::{Align, Box, Button, Entry, Label, Orientation, PasswordEntry};
-use libadwaita::{prelude::*, ApplicationWindow, HeaderBar, Window};
+use gtk4::{
+    Align, Box, Button, CheckButton, Entry, Label, ListBox, Orientation, PasswordEntry, PolicyType,
+    ScrolledWindow, SelectionMode,
+};
+use libadwaita::{gio, prelude::*, ApplicationWindow, HeaderBar, Window};
 
 use crate::application::IvyApplication;
+use crate::ssh::{AuthPrompt, SshPrompter};
+use crate::tmux_api::{list_sessions, AttachMode, TmuxSession};
 
 pub fn spawn_rename_modal(parent: &ApplicationWindow, old_name: &str, callback: RustClosure) {
     let app = parent.application().unwrap();
@@ -69,12 +76,6 @@ pub fn spawn_new_tmux_modal(parent: &ApplicationWindow) {
         .margin_top(10)
         .build();
 
-    // Tmux session input
-    let session_label = Label::new(Some("Tmux session:"));
-    let session_input = Entry::new();
-    content.append(&session_label);
-    content.append(&session_input);
-
     // SSH input
     let ssh_label = Label::new(Some("SSH host (optional):"));
     let ssh_input = Entry::new();
@@ -82,48 +83,450 @@ pub fn spawn_new_tmux_modal(parent: &ApplicationWindow) {
     content.append(&ssh_label);
     content.append(&ssh_input);
 
-    // SSH password
-    let password_label = Label::new(Some("SSH password (optional, public key preferred):"));
-    let password_input = PasswordEntry::new();
-    content.append(&password_label);
-    content.append(&password_input);
+    // Existing sessions
+    let sessions_label = Label::builder()
+        .label("Existing sessions:")
+        .halign(Align::Start)
+        .hexpand(true)
+        .build();
+    let refresh_button = Button::builder().label("Refresh").build();
+    let sessions_header = Box::new(Orientation::Horizontal, 5);
+    sessions_header.append(&sessions_label);
+    sessions_header.append(&refresh_button);
+    content.append(&sessions_header);
 
-    // Button
-    let button = Button::builder().label("Attach").build();
-    content.append(&button);
+    let placeholder = Label::builder()
+        .label("Loading sessions...")
+        .margin_top(10)
+        .margin_bottom(10)
+        .build();
+    let session_list = ListBox::builder()
+        .selection_mode(SelectionMode::Single)
+        .css_classes(["boxed-list"])
+        .build();
+    session_list.set_placeholder(Some(&placeholder));
+    let scrolled_window = ScrolledWindow::builder()
+        .child(&session_list)
+        .hscrollbar_policy(PolicyType::Never)
+        .min_content_height(150)
+        .build();
+    content.append(&scrolled_window);
+
+    let attach_button = Button::builder()
+        .label("Attach to selected session")
+        .sensitive(false)
+        .build();
+    content.append(&attach_button);
+
+    // New session
+    let session_label = Label::new(Some("New session name (optional):"));
+    let session_input = Entry::new();
+    let create_button = Button::builder().label("Create new session").build();
+    content.append(&session_label);
+    content.append(&session_input);
+    content.append(&create_button);
 
     let window_box = Box::new(Orientation::Vertical, 0);
     window_box.append(&header_bar);
     window_box.append(&content);
     dialog.set_content(Some(&window_box));
 
-    button.connect_clicked(glib::clone!(
+    // Sessions shown in the list, in the same order as the rows
+    let sessions: Rc<RefCell<Vec<TmuxSession>>> = Rc::new(RefCell::new(vec![]));
+
+    session_list.connect_selected_rows_changed(glib::clone!(
+        #[weak]
+        attach_button,
+        move |session_list| {
+            attach_button.set_sensitive(session_list.selected_row().is_some());
+        }
+    ));
+
+    let attach_selected = glib::clone!(
+        #[weak]
+        dialog,
+        #[weak]
+        session_list,
+        #[weak]
+        ssh_input,
+        #[strong]
+        sessions,
+        move || {
+            let row = match session_list.selected_row() {
+                Some(row) => row,
+                None => return,
+            };
+            let tmux_session = match sessions.borrow().get(row.index() as usize) {
+                Some(session) => session.name.clone(),
+                None => return,
+            };
+
+            open_tmux_window(
+                &dialog,
+                &tmux_session,
+                AttachMode::Attach,
+                &ssh_input.text(),
+            );
+        }
+    );
+    let attach_selected = Rc::new(attach_selected);
+    attach_button.connect_clicked(glib::clone!(
+        #[strong]
+        attach_selected,
+        move |_| attach_selected()
+    ));
+    session_list.connect_row_activated(move |_, _| attach_selected());
+
+    create_button.connect_clicked(glib::clone!(
         #[weak]
         dialog,
+        #[weak]
+        ssh_input,
         move |_| {
-            let tmux_session = session_input.text();
-            let ssh_target = ssh_input.text();
-            let ssh_password = password_input.text();
+            open_tmux_window(
+                &dialog,
+                &session_input.text(),
+                AttachMode::Create,
+                &ssh_input.text(),
+            );
+        }
+    ));
 
-            let app = dialog.application();
-            dialog.close();
+    refresh_button.connect_clicked(glib::clone!(
+        #[weak]
+        session_list,
+        #[weak]
+        placeholder,
+        #[weak]
+        ssh_input,
+        #[strong]
+        sessions,
+        move |_| {
+            refresh_session_list(
+                &session_list,
+                &placeholder,
+                &sessions,
+                ssh_input.text().to_string(),
+            );
+        }
+    ));
 
-            if let Some(app) = app {
-                let app: IvyApplication = app.downcast().unwrap();
-                let ssh_target = if ssh_target.is_empty() {
-                    None
-                } else {
-                    Some((ssh_target.as_str(), ssh_password.as_str()))
+    // Local sessions can be listed right away
+    refresh_session_list(&session_list, &placeholder, &sessions, String::new());
+
+    dialog.present();
+}
+
+fn refresh_session_list(
+    session_list: &ListBox,
+    placeholder: &Label,
+    sessions: &Rc<RefCell<Vec<TmuxSession>>>,
+    ssh_target: String,
+) {
+    // Remove old rows
+    while let Some(row) = session_list.row_at_index(0) {
+        session_list.remove(&row);
+    }
+    sessions.borrow_mut().clear();
+    placeholder.set_label("Loading sessions...");
+
+    // Authentication prompts are shown on top of the dialog listing sessions
+    let prompter = session_list
+        .root()
+        .and_then(|root| root.downcast::<gtk4::Window>().ok())
+        .map(|dialog| new_ssh_prompter(&dialog));
+
+    glib::spawn_future_local(glib::clone!(
+        #[weak]
+        session_list,
+        #[weak]
+        placeholder,
+        #[strong]
+        sessions,
+        async move {
+            let ret = gio::spawn_blocking(move || {
+                let ssh_host = match (ssh_target.is_empty(), &prompter) {
+                    (false, Some(prompter)) => Some((ssh_target.as_str(), prompter)),
+                    _ => None,
                 };
-                app.new_tmux_window(tmux_session.as_str(), ssh_target);
+                list_sessions(ssh_host)
+            })
+            .await;
+
+            let found = match ret {
+                Ok(Ok(found)) => found,
+                Ok(Err(err)) => {
+                    placeholder.set_label(&format!("Could not list sessions: {}", err));
+                    return;
+                }
+                Err(_) => {
+                    placeholder.set_label("Could not list sessions");
+                    return;
+                }
+            };
+
+            placeholder.set_label("No sessions found");
+            for session in found.iter() {
+                session_list.append(&session_row(session));
             }
+            sessions.replace(found);
         }
     ));
+}
+
+fn session_row(session: &TmuxSession) -> Box {
+    let name = Label::builder()
+        .label(&session.name)
+        .halign(Align::Start)
+        .hexpand(true)
+        .build();
+
+    let mut details = match session.windows {
+        1 => String::from("1 window"),
+        windows => format!("{} windows", windows),
+    };
+    if session.attached > 0 {
+        details.push_str(", attached");
+    }
+    let last_activity = glib::DateTime::from_unix_local(session.activity)
+        .and_then(|date_time| date_time.format("%Y-%m-%d %H:%M"));
+    if let Ok(last_activity) = last_activity {
+        details.push_str(&format!(", last active {}", last_activity));
+    }
+    let details = Label::builder()
+        .label(&details)
+        .css_classes(["dim-label"])
+        .build();
+
+    let row = Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(10)
+        .margin_top(5)
+        .margin_bottom(5)
+        .margin_start(5)
+        .margin_end(5)
+        .build();
+    row.append(&name);
+    row.append(&details);
+    row
+}
+
+fn open_tmux_window(
+    dialog: &Window,
+    tmux_session: &str,
+    attach_mode: AttachMode,
+    ssh_target: &str,
+) {
+    let app = dialog.application();
+    dialog.close();
+
+    if let Some(app) = app {
+        let app: IvyApplication = app.downcast().unwrap();
+        let ssh_target = if ssh_target.is_empty() {
+            None
+        } else {
+            Some(ssh_target)
+        };
+        app.new_tmux_window(tmux_session, attach_mode, ssh_target);
+    }
+}
+
+/// Creates a prompter, whose authentication prompts are shown as dialogs on top
+/// of the parent
+pub fn new_ssh_prompter(parent: &impl IsA<gtk4::Window>) -> SshPrompter {
+    let (prompter, prompts) = SshPrompter::new();
+    let parent = parent.upcast_ref::<gtk4::Window>().downgrade();
+
+    // Future ends once the connecting thread drops the prompter
+    glib::spawn_future_local(async move {
+        while let Ok(prompt) = prompts.recv().await {
+            match parent.upgrade() {
+                Some(parent) => spawn_ssh_prompt_modal(&parent, prompt),
+                None => prompt.reply(None),
+            }
+        }
+    });
+
+    prompter
+}
+
+fn spawn_ssh_prompt_modal(parent: &gtk4::Window, prompt: AuthPrompt) {
+    let app = parent.application().unwrap();
+
+    let dialog = Window::builder()
+        .application(&app)
+        .title("SSH authentication")
+        .modal(true)
+        .transient_for(parent)
+        .build();
+
+    let header_bar = HeaderBar::new();
+    let content = Box::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(5)
+        .margin_bottom(10)
+        .margin_top(10)
+        .margin_end(10)
+        .margin_start(10)
+        .build();
+
+    if !prompt.instructions.is_empty() {
+        let instructions = Label::builder()
+            .label(&prompt.instructions)
+            .halign(Align::Start)
+            .build();
+        content.append(&instructions);
+    }
+
+    // Answers which should not be visible (passwords) are hidden while typing
+    let mut entries: Vec<gtk4::Editable> = Vec::with_capacity(prompt.prompts.len());
+    for (text, echo) in prompt.prompts.iter() {
+        let label = Label::builder().label(text).halign(Align::Start).build();
+        content.append(&label);
+
+        let entry: gtk4::Editable = if *echo {
+            Entry::builder().activates_default(true).build().upcast()
+        } else {
+            PasswordEntry::builder()
+                .activates_default(true)
+                .build()
+                .upcast()
+        };
+        content.append(&entry);
+        entries.push(entry);
+    }
+
+    let cancel = Button::builder().label("Cancel").build();
+    let confirm = Button::builder()
+        .label("Continue")
+        .css_classes(["suggested-action"])
+        .build();
+    let buttons = Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(5)
+        .halign(Align::End)
+        .build();
+    buttons.append(&cancel);
+    buttons.append(&confirm);
+    content.append(&buttons);
+
+    let window_box = Box::new(Orientation::Vertical, 0);
+    window_box.append(&header_bar);
+    window_box.append(&content);
+    dialog.set_content(Some(&window_box));
+    dialog.set_default_widget(Some(&confirm));
+
+    let first_entry = entries.first().cloned();
+    let prompt = Rc::new(prompt);
+    confirm.connect_clicked(glib::clone!(
+        #[weak]
+        dialog,
+        #[strong]
+        prompt,
+        move |_| {
+            let answers = entries
+                .iter()
+                .map(|entry| entry.text().to_string())
+                .collect();
+            prompt.reply(Some(answers));
+            dialog.close();
+        }
+    ));
+    cancel.connect_clicked(glib::clone!(
+        #[weak]
+        dialog,
+        move |_| {
+            dialog.close();
+        }
+    ));
+    // Closing the dialog without answering cancels authentication
+    dialog.connect_close_request(move |_| {
+        prompt.reply(None);
+        glib::Propagation::Proceed
+    });
 
     dialog.present();
+    if let Some(entry) = first_entry {
+        entry.grab_focus();
+    }
 }
 
 pub fn spawn_exit_modal(parent: &ApplicationWindow, confirm_callback: RustClosure) -> Window {
+    let confirm = Button::builder().label("Close Terminals").build();
+    let dialog = build_close_modal(
+        parent,
+        "Close multiple terminals?",
+        "This window has several terminals open. Closing the window will\n also close all terminals within it.",
+        None,
+        &[&confirm],
+    );
+
+    confirm.connect_clicked(glib::clone!(
+        #[weak]
+        parent,
+        #[weak]
+        dialog,
+        move |_| {
+            confirm_callback.invoke::<()>(&[]);
+            dialog.close();
+            parent.close();
+        }
+    ));
+
+    dialog.present();
+    dialog
+}
+
+/// Asks whether the Tmux session should keep running after the window closes.
+/// Callback is given whether the session should be killed and whether the choice
+/// should be remembered.
+pub fn spawn_tmux_exit_modal(parent: &ApplicationWindow, callback: RustClosure) -> Window {
+    let remember = CheckButton::builder()
+        .label("Remember my choice")
+        .halign(Align::Center)
+        .build();
+    let detach = Button::builder().label("Detach").build();
+    let kill = Button::builder()
+        .label("Kill Session")
+        .css_classes(["destructive-action"])
+        .build();
+    let dialog = build_close_modal(
+        parent,
+        "Keep the Tmux session running?",
+        "Detaching leaves the session and its programs running, so you can\n attach to it later. Killing the session ends all programs within it.",
+        Some(remember.upcast_ref()),
+        &[&detach, &kill],
+    );
+
+    let callback = Rc::new(callback);
+    for (button, kill) in [(detach, false), (kill, true)] {
+        button.connect_clicked(glib::clone!(
+            #[weak]
+            dialog,
+            #[weak]
+            remember,
+            #[strong]
+            callback,
+            move |_| {
+                callback.invoke::<()>(&[&kill, &remember.is_active()]);
+                dialog.close();
+            }
+        ));
+    }
+
+    dialog.present();
+    dialog
+}
+
+/// Builds (but does not present) a close confirmation dialog, with a Cancel
+/// button followed by the given buttons
+fn build_close_modal(
+    parent: &ApplicationWindow,
+    heading: &str,
+    message: &str,
+    extra: Option<&gtk4::Widget>,
+    buttons: &[&Button],
+) -> Window {
     let app = parent.application().unwrap();
 
     let dialog = Window::builder()
@@ -161,45 +564,37 @@ pub fn spawn_exit_modal(parent: &ApplicationWindow, confirm_callback: RustClosur
             dialog.close();
         }
     ));
-    let confirm = Button::builder().label("Close Terminals").build();
-    confirm.connect_clicked(glib::clone!(
-        #[weak]
-        parent,
-        #[weak]
-        dialog,
-        move |_| {
-            confirm_callback.invoke::<()>(&[]);
-            dialog.close();
-            parent.close();
-        }
-    ));
 
-    let buttons = Box::builder()
+    let button_box = Box::builder()
         .orientation(Orientation::Horizontal)
         .spacing(5)
         .halign(Align::Center)
         .build();
-    buttons.append(&cancel);
-    buttons.append(&confirm);
+    button_box.append(&cancel);
+    for button in buttons {
+        button_box.append(*button);
+    }
 
     // Labels
     let heading = Label::builder()
-        .label("Close multiple terminals?")
+        .label(heading)
         .css_classes(["close_confirm_heading"])
         .build();
     let message = Label::builder()
-        .label("This window has several terminals open. Closing the window will\n also close all terminals within it.")
+        .label(message)
         .justify(gtk4::Justification::Center)
         .margin_start(20)
         .margin_end(20)
         .build();
     content.append(&heading);
     content.append(&message);
-    content.append(&buttons);
+    if let Some(extra) = extra {
+        content.append(extra);
+    }
+    content.append(&button_box);
 
     window_box.append(&content);
     dialog.set_content(Some(&window_box));
-    dialog.present();
 
     dialog
 }
@@ -82,7 +82,7 @@ impl LayoutManagerImpl for ContainerLayoutPriv {
         let mut allocation_iter = allocations.iter();
         while let Some(child) = children_iter {
             let allocation = allocation_iter.next().unwrap();
-            child.size_allocate(&allocation, -1);
+            child.size_allocate(allocation, -1);
             children_iter = child.next_sibling();
         }
     }
@@ -48,7 +48,7 @@ impl Separator {
         // Change the cursor when hovering separator and container
         let cursor = Cursor::from_name(cursor, None);
         if let Some(cursor) = cursor.as_ref() {
-            bin.set_cursor(Some(&cursor));
+            bin.set_cursor(Some(cursor));
         }
 
         bin
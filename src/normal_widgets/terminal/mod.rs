@@ -125,8 +125,9 @@ impl Terminal {
                 if let Some(event) = eventctl.current_event() {
                     // Check if pressed keys match a keybinding
                     if let Some(action) = app.handle_keyboard_event(event) {
-                        handle_keyboard(action, &terminal, &top_level, &vte);
-                        return Propagation::Stop;
+                        if handle_keyboard(action, &terminal, &top_level, &vte) {
+                            return Propagation::Stop;
+                        }
                     }
                 }
                 Propagation::Proceed
@@ -223,8 +224,14 @@ impl Terminal {
     }
 }
 
+/// Returns false if the action is not supported, so the keys reach the shell
 #[inline]
-fn handle_keyboard(action: KeyboardAction, terminal: &Terminal, top_level: &TopLevel, vte: &Vte) {
+fn handle_keyboard(
+    action: KeyboardAction,
+    terminal: &Terminal,
+    top_level: &TopLevel,
+    vte: &Vte,
+) -> bool {
     match action {
         KeyboardAction::PaneSplit(vertical) => {
             let orientation = if vertical {
@@ -270,5 +277,21 @@ fn handle_keyboard(action: KeyboardAction, terminal: &Terminal, top_level: &TopL
             let clear_scrollback = [b'\x1b', b'[', b'3', b'J'];
             vte.feed(&clear_scrollback);
         }
+        KeyboardAction::PaneSwap(_)
+        | KeyboardAction::PaneBreak
+        | KeyboardAction::PaneJoin
+        | KeyboardAction::TabRotate
+        | KeyboardAction::TabLayout(_)
+        | KeyboardAction::TabNextLayout
+        | KeyboardAction::TabLayoutSave
+        | KeyboardAction::TabLayoutRestore
+        | KeyboardAction::TabSynchronize
+        | KeyboardAction::SessionDetach
+        | KeyboardAction::SessionKill => {
+            // Rearranging panes and sessions are only supported by Tmux
+            return false;
+        }
     }
+
+    true
 }
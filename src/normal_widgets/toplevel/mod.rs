@@ -61,7 +61,7 @@ impl TopLevel {
         self.unzoom();
 
         let window = borrow_clone(&self.imp().window);
-        let new_terminal = Terminal::new(&self, &window, None);
+        let new_terminal = Terminal::new(self, &window, None);
 
         let parent = terminal.parent().unwrap();
         if parent.eq(self) {
@@ -91,7 +91,7 @@ impl TopLevel {
         new_container.append(terminal);
         new_container.append(&new_terminal);
 
-        return (new_terminal, Some(new_container));
+        (new_terminal, Some(new_container))
     }
 
     pub fn close_pane(&self, closing_terminal: &Terminal) {
@@ -274,7 +274,7 @@ impl TopLevel {
         lru_terminals.insert(
             0,
             WithId {
-                id: id,
+                id,
                 terminal: terminal.clone(),
             },
         );
@@ -282,10 +282,9 @@ impl TopLevel {
 
     pub fn lru_terminal(&self) -> Option<Terminal> {
         let lru_terminals = self.imp().lru_terminals.borrow();
-        match lru_terminals.first() {
-            Some(id_terminal) => Some(id_terminal.terminal.clone()),
-            None => None,
-        }
+        lru_terminals
+            .first()
+            .map(|id_terminal| id_terminal.terminal.clone())
     }
 
     pub fn find_neighbor(
@@ -393,7 +392,7 @@ impl TopLevel {
         }
 
         // We ditch the user@host:... part of the title
-        if let Some(name) = name.split(':').last() {
+        if let Some(name) = name.split(':').next_back() {
             let tab_view = borrow_clone(&imp.tab_view);
             // TODO: Just store the Page directly instead of tab_view
             let page = tab_view.page(self);
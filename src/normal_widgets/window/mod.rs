@@ -182,7 +182,7 @@ impl IvyNormalWindow {
     pub fn register_terminal(&self, pane_id: u32, terminal: &Terminal) {
         let imp = self.imp();
         let mut terminals = imp.terminals.borrow_mut();
-        terminals.insert(pane_id, &terminal);
+        terminals.insert(pane_id, terminal);
         debug!("Terminal with ID {} registered", pane_id);
     }
 
@@ -85,7 +85,7 @@ impl KeybindingPage {
         let old_listener = imp.listening.get();
 
         // If listen is false, it means we stop listening in any case
-        if listen == false {
+        if !listen {
             if let Some(old_listener) = old_listener {
                 // Stop listening
                 imp.enable_keyboard(false);
@@ -201,7 +201,7 @@ fn create_keybinding_row(
         page,
         move |row| {
             // Stop listening if row loses focus
-            if row.has_focus() == false {
+            if !row.has_focus() {
                 page.row_listening_changed(idx, false);
             }
         }
@@ -1,8 +1,9 @@
 use std::{cell::RefCell, rc::Rc};
 
+use gtk4::DropDown;
 use libadwaita::{prelude::*, PreferencesGroup, PreferencesPage};
 
-use crate::config::GlobalConfig;
+use crate::config::{GlobalConfig, TmuxCloseAction};
 
 use super::{create_color_button, create_setting_row};
 
@@ -28,12 +29,50 @@ fn create_appearance_prefs(config: &Rc<RefCell<GlobalConfig>>) -> PreferencesGro
     tmux_colors
 }
 
+/// Choices of the close action dropdown, in order
+const CLOSE_ACTIONS: [(TmuxCloseAction, &str); 3] = [
+    (TmuxCloseAction::Ask, "Ask every time"),
+    (TmuxCloseAction::Detach, "Detach"),
+    (TmuxCloseAction::Kill, "Kill session"),
+];
+
+fn create_session_prefs(config: &Rc<RefCell<GlobalConfig>>) -> PreferencesGroup {
+    let borrowed = config.borrow();
+
+    // What happens to the session when closing the window
+    let names: Vec<&str> = CLOSE_ACTIONS.iter().map(|(_, name)| *name).collect();
+    let close_action = DropDown::from_strings(&names);
+    let selected = CLOSE_ACTIONS
+        .iter()
+        .position(|(action, _)| *action == borrowed.tmux.close_action)
+        .unwrap_or_default();
+    close_action.set_selected(selected as u32);
+    close_action.connect_selected_notify(glib::clone!(
+        #[weak]
+        config,
+        move |dropdown| {
+            if let Some((action, _)) = CLOSE_ACTIONS.get(dropdown.selected() as usize) {
+                let mut borrowed = config.borrow_mut();
+                borrowed.tmux.close_action = *action;
+            }
+        }
+    ));
+
+    let session_prefs = PreferencesGroup::builder().title("Session").build();
+
+    create_setting_row(&session_prefs, "When closing the window", close_action);
+
+    session_prefs
+}
+
 pub fn create_tmux_page(config: &Rc<RefCell<GlobalConfig>>) -> PreferencesPage {
     // Page 2: Tmux settings
     let page = PreferencesPage::builder().title("Tmux").build();
 
     let appearance_prefs = create_appearance_prefs(config);
     page.add(&appearance_prefs);
+    let session_prefs = create_session_prefs(config);
+    page.add(&session_prefs);
 
     page
 }
@@ -1,6 +1,10 @@
 use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
 use std::io::{self, ErrorKind, Read, Write};
 use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use async_channel::{Receiver, Sender};
 use enumflags2::{bitflags, BitFlags};
@@ -8,32 +12,61 @@ use glib::JoinHandle;
 use gtk4::gio::spawn_blocking;
 use gtk4::Orientation;
 use log::debug;
-use receive::tmux_parse_data;
+use receive::{command_failed_events, tmux_parse_data};
+use send::TmuxWriter;
 use ssh2::{DisconnectCode, Session};
 use vmap::io::{Ring, SeqWrite};
 
 use crate::helpers::{IvyError, TmuxError};
-use crate::keyboard::Direction;
+use crate::keyboard::{Direction, TmuxKey};
 use crate::ssh::{SSHData, SSH_TOKEN};
 use crate::tmux_widgets::IvyTmuxWindow;
 
 mod parse_layout;
 mod receive;
+#[cfg(test)]
+mod replay;
 mod send;
+mod sessions;
+
+pub use sessions::{list_sessions, TmuxSession};
+
+/// Maximum number of Tmux events waiting to be handled by the main thread. Once
+/// full, the parser stops reading Tmux output, which lets Tmux pause the panes
+const EVENT_QUEUE_SIZE: usize = 1024;
+/// Tmux pauses panes whose output is this many seconds behind
+const PAUSE_AFTER_SECONDS: u32 = 3;
+/// Lines of history captured when attaching, older lines are loaded on demand
+pub const INITIAL_HISTORY_LINES: u32 = 1000;
+/// Lines of history loaded each time the user scrolls to the top
+pub const HISTORY_PAGE_LINES: u32 = 2000;
+/// How long Tmux has to start answering a command before we stop waiting for it
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(15);
+/// How often commands are checked for timeouts, even if Tmux sends nothing
+const COMMAND_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct TmuxAPI {
     ssh_session: Option<Session>,
-    stdin_stream: RefCell<Box<dyn Write>>,
-    command_queue: Sender<TmuxCommand>,
+    ssh_target: Option<String>,
+    writer: Rc<RefCell<TmuxWriter>>,
+    /// Reports input which could not be written once retried later
+    write_error_sender: Sender<TmuxEvent>,
+    commands: Arc<Mutex<CommandTracker>>,
     window_size: Cell<(i32, i32)>,
     resize_future: Cell<bool>,
+    /// Keystrokes typed while earlier ones are on their way, sent in a single batch
+    pending_keys: RefCell<Vec<(u32, TmuxKey)>>,
+    /// Keypress commands Tmux has not answered yet
+    keys_in_flight: Cell<usize>,
     receive_future: JoinHandle<()>,
+    sweep_future: JoinHandle<()>,
 }
 
 impl Drop for TmuxAPI {
     fn drop(&mut self) {
-        // Stop main-thread future which receives Tmux events
+        // Stop main-thread futures which receive Tmux events and time out commands
         self.receive_future.abort();
+        self.sweep_future.abort();
         // Disconnect SSH session if any
         if let Some(ssh_session) = &self.ssh_session {
             if let Err(err) =
@@ -45,61 +78,248 @@ impl Drop for TmuxAPI {
     }
 }
 
+/// Whether we attach to an existing session or create a new one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttachMode {
+    Attach,
+    Create,
+}
+
+#[derive(Debug)]
 pub struct LayoutSync {
     pub tab_id: u32,
     pub layout: Vec<TmuxPane>,
     pub visible_layout: Vec<TmuxPane>,
     pub flags: BitFlags<LayoutFlags>,
+    /// Unparsed `window_layout`, which can be given back to `select-layout`
+    pub window_layout: String,
+    /// Only known when the layout was requested by us, not in %layout-change
+    pub index: Option<u32>,
     pub name: Option<String>,
 }
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum TmuxCommand {
     Init,
     InitialLayout,
     Keypress,
+    FlowControl,
+    SessionList,
+    SessionSwitch(u32),
     TabNew,
+    TabLayout(u32),
     TabClose,
     TabSelect(u32),
     TabRename(u32),
+    TabMove(u32),
     PaneSplit(bool),
     PaneClose(u32),
     PaneSelect(u32),
-    PaneCurrentPath(u32),
     PaneMoveFocus(Direction),
     PaneZoom(u32),
     PaneResize(u32),
+    PaneSwap(u32),
+    PaneBreak(u32),
+    PaneJoin(u32),
+    TabRotate(u32),
+    /// Preset or next layout, for the Tab of the given pane
+    TabSelectLayout(u32),
+    /// Saved layout, for the given Tab
+    TabApplyLayout(u32),
+    TabSynchronize(u32),
+    Detach,
+    SessionKill,
     ChangeSize(i32, i32),
     InitialOutput(u32),
-    ClipboardPaste,
+    PaneContinue(u32),
+    PaneCatchUp(u32),
+    PaneCursor(u32),
+    PaneMouseMode(u32),
+    PaneHistory(u32, HistoryPart),
+    PaneHistorySize(u32),
+    MouseReport,
+    PasteChunk(u32),
+    ClipboardPaste(u32),
     ClearScrollback(u32),
+    Subscribe,
+}
+
+/// Part of the pane captured while loading older history, see
+/// `TmuxAPI::get_pane_history()`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HistoryPart {
+    Size,
+    Older,
+    Recent,
+    Screen,
+    Cursor,
+}
+
+/// Lines of history to capture, counted back from the first line of the screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryRange {
+    /// Oldest and newest line of the older page, if there is one
+    pub older: Option<(u32, u32)>,
+    /// Newest lines of the history, which the Terminal shows but we have not
+    /// captured yet
+    pub recent: u32,
+}
+
+#[derive(Debug)]
+pub enum HistoryCapture {
+    /// History size and history limit of the pane
+    Size(u32, u32),
+    /// Decoded lines of a capture
+    Lines(HistoryPart, Vec<Vec<u8>>),
+    /// Escape sequence which moves the cursor back to its position
+    Cursor(Vec<u8>),
+}
+
+impl TmuxCommand {
+    /// Human readable description, used when reporting errors to the user
+    pub fn description(&self) -> &'static str {
+        match self {
+            TmuxCommand::Init => "Attaching to session",
+            TmuxCommand::FlowControl => "Enabling flow control",
+            TmuxCommand::InitialLayout => "Getting layout",
+            TmuxCommand::Keypress => "Sending keys",
+            TmuxCommand::SessionList => "Listing sessions",
+            TmuxCommand::SessionSwitch(_) => "Switching session",
+            TmuxCommand::TabNew => "Creating tab",
+            TmuxCommand::TabLayout(_) => "Getting tab layout",
+            TmuxCommand::TabClose => "Closing tab",
+            TmuxCommand::TabSelect(_) => "Selecting tab",
+            TmuxCommand::TabRename(_) => "Renaming tab",
+            TmuxCommand::TabMove(_) => "Moving tab",
+            TmuxCommand::PaneSplit(_) => "Splitting pane",
+            TmuxCommand::PaneClose(_) => "Closing pane",
+            TmuxCommand::PaneSelect(_) => "Selecting pane",
+            TmuxCommand::PaneMoveFocus(_) => "Moving focus",
+            TmuxCommand::PaneZoom(_) => "Zooming pane",
+            TmuxCommand::PaneResize(_) => "Resizing pane",
+            TmuxCommand::PaneSwap(_) => "Swapping panes",
+            TmuxCommand::PaneBreak(_) => "Moving pane to a new tab",
+            TmuxCommand::PaneJoin(_) => "Moving pane to another tab",
+            TmuxCommand::TabRotate(_) => "Rotating panes",
+            TmuxCommand::TabSelectLayout(_) => "Changing tab layout",
+            TmuxCommand::TabApplyLayout(_) => "Restoring tab layout",
+            TmuxCommand::TabSynchronize(_) => "Toggling synchronized panes",
+            TmuxCommand::Detach => "Detaching from session",
+            TmuxCommand::SessionKill => "Killing session",
+            TmuxCommand::ChangeSize(_, _) => "Resizing client",
+            TmuxCommand::InitialOutput(_) => "Capturing pane output",
+            TmuxCommand::PaneContinue(_) => "Resuming pane",
+            TmuxCommand::PaneCatchUp(_) => "Capturing pane screen",
+            TmuxCommand::PaneCursor(_) => "Getting cursor position",
+            TmuxCommand::PaneMouseMode(_) => "Getting mouse mode",
+            TmuxCommand::PaneHistory(_, _) => "Loading pane history",
+            TmuxCommand::PaneHistorySize(_) => "Getting history size",
+            TmuxCommand::MouseReport => "Sending mouse event",
+            TmuxCommand::PasteChunk(_) => "Copying clipboard to Tmux",
+            TmuxCommand::ClipboardPaste(_) => "Pasting clipboard",
+            TmuxCommand::ClearScrollback(_) => "Clearing scrollback",
+            TmuxCommand::Subscribe => "Subscribing to changes",
+        }
+    }
 }
 
+#[derive(Debug)]
 pub enum TmuxEvent {
     ScrollOutput(u32, usize),
     InitialLayout(LayoutSync),
     InitialLayoutFinished,
+    InitialOutputStarted(u32),
     InitialOutputFinished(u32),
     LayoutChanged(LayoutSync),
     Output(u32, Vec<u8>, bool),
+    PanePaused(u32),
+    PaneCaughtUp(u32),
+    PaneHistorySize(u32, u32),
+    PaneHistoryCaptured(u32, HistoryCapture),
+    PaneHistoryLoaded(u32),
+    PanePropertyChanged(u32, PaneProperty, String),
     SizeChanged,
     PaneFocusChanged(u32, u32),
     TabFocusChanged(u32),
+    TabAdded(u32),
     TabNew(LayoutSync),
     TabClosed(u32),
     TabRenamed(u32, String),
+    TabFlagsChanged(u32, BitFlags<LayoutFlags>),
+    TabIndexChanged(u32, u32),
     SessionChanged(u32, String),
-    Exit,
+    SessionList(Vec<TmuxSession>),
+    PasteChunkSent(u32),
+    KeypressSent,
+    PasteFailed(u32),
+    CommandFailed(String),
+    TabSynchronizeChanged(u32, bool),
+    Exit(Option<String>),
+    /// SSH connection dropped, while the Tmux session may still be alive
+    Disconnected,
     ScrollbackCleared(u32),
 }
 
+/// Subscription to `window_flags` of all windows, used for activity, bell and
+/// silence alerts
+pub const WINDOW_FLAGS_SUBSCRIPTION: &str = "window-flags";
+/// Subscription to `window_index` of all windows, so Tabs follow the Tmux order
+pub const WINDOW_INDEX_SUBSCRIPTION: &str = "window-index";
+/// Subscription to the `synchronize-panes` option of all windows
+pub const WINDOW_SYNC_SUBSCRIPTION: &str = "window-sync";
+
+/// Pane metadata Tmux reports using format subscriptions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaneProperty {
+    CurrentPath,
+    CurrentCommand,
+    Title,
+}
+
+impl PaneProperty {
+    pub const ALL: [PaneProperty; 3] = [
+        PaneProperty::CurrentPath,
+        PaneProperty::CurrentCommand,
+        PaneProperty::Title,
+    ];
+
+    /// Name of the subscription, which Tmux includes in %subscription-changed
+    pub fn subscription_name(&self) -> &'static str {
+        match self {
+            PaneProperty::CurrentPath => "pane-path",
+            PaneProperty::CurrentCommand => "pane-command",
+            PaneProperty::Title => "pane-title",
+        }
+    }
+
+    pub fn format(&self) -> &'static str {
+        match self {
+            PaneProperty::CurrentPath => "#{pane_current_path}",
+            PaneProperty::CurrentCommand => "#{pane_current_command}",
+            PaneProperty::Title => "#{pane_title}",
+        }
+    }
+
+    pub fn from_subscription_name(name: &str) -> Option<Self> {
+        PaneProperty::ALL
+            .into_iter()
+            .find(|property| property.subscription_name() == name)
+    }
+}
+
 #[bitflags]
 #[repr(u8)]
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum LayoutFlags {
     HasFocus,
     IsZoomed,
+    /// Previously focused window
+    IsLast,
+    Activity,
+    Bell,
+    Silence,
+    Marked,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -118,30 +338,163 @@ pub enum TmuxPane {
     Return,
 }
 
+/// Command which was sent to Tmux, but its output block has not started yet
+struct PendingCommand {
+    command: TmuxCommand,
+    deadline: Instant,
+    /// Already reported as timed out, so its block is ignored once it arrives
+    expired: bool,
+}
+
+/// Commands sent to Tmux which are waiting for their output block. Shared
+/// between the main thread, which sends commands and times them out, and the
+/// parser thread, which matches them to blocks.
+///
+/// Tmux answers lines in the order they were sent. Each command on a line gets
+/// a block with its own number, and these numbers are consecutive, unless a
+/// hook runs in between. Once a command fails (or the line fails to parse), the
+/// rest of the line is not run.
+#[derive(Default)]
+struct CommandTracker {
+    /// Lines whose first block has not started yet
+    lines: VecDeque<Vec<PendingCommand>>,
+    /// Remaining commands of the line Tmux is answering, keyed by the number
+    /// Tmux will report in their %begin
+    numbered: HashMap<u32, PendingCommand>,
+}
+
+impl CommandTracker {
+    /// Commands are expected to be written to Tmux on a single line
+    fn push_line(&mut self, commands: Vec<TmuxCommand>) {
+        let deadline = Instant::now() + COMMAND_TIMEOUT;
+        let line = commands
+            .into_iter()
+            .map(|command| PendingCommand {
+                command,
+                deadline,
+                expired: false,
+            })
+            .collect();
+        self.lines.push_back(line);
+    }
+
+    /// Returns the command answered by the block with given number. Commands
+    /// which Tmux skipped are appended to `lost`.
+    fn begin_block(
+        &mut self,
+        number: u32,
+        ours: bool,
+        lost: &mut Vec<TmuxCommand>,
+    ) -> Option<TmuxCommand> {
+        if !ours {
+            // Hook which runs in between our commands takes up a number
+            if self.numbered.contains_key(&number) {
+                self.shift_numbers(number);
+            }
+            return None;
+        }
+
+        if !self.numbered.contains_key(&number) {
+            // This is the first block of the next line, so anything we were still
+            // waiting for on the previous line will never be answered
+            lost.extend(self.abandon_line());
+
+            let line = match self.lines.pop_front() {
+                Some(line) => line,
+                None => {
+                    debug!("Tmux started block {} for a command we never sent", number);
+                    return None;
+                }
+            };
+            for (offset, pending) in line.into_iter().enumerate() {
+                let pending_number = number.wrapping_add(offset as u32);
+                self.numbered.insert(pending_number, pending);
+            }
+        }
+
+        let pending = self.numbered.remove(&number)?;
+        if pending.expired {
+            debug!("Ignoring late reply to {:?}", pending.command);
+            return None;
+        }
+        Some(pending.command)
+    }
+
+    /// Moves commands expected in block `from` or later to the following block
+    fn shift_numbers(&mut self, from: u32) {
+        let shifted: Vec<(u32, PendingCommand)> = self
+            .take_numbered()
+            .enumerate()
+            .map(|(offset, pending)| (from.wrapping_add(offset as u32 + 1), pending))
+            .collect();
+        self.numbered.extend(shifted);
+    }
+
+    /// Removes all remaining commands of the line being answered, in order
+    fn take_numbered(&mut self) -> impl Iterator<Item = PendingCommand> {
+        let mut numbered: Vec<(u32, PendingCommand)> = self.numbered.drain().collect();
+        numbered.sort_by_key(|(number, _)| *number);
+        numbered.into_iter().map(|(_, pending)| pending)
+    }
+
+    /// Removes remaining commands of the line being answered, which Tmux is not
+    /// going to run
+    fn abandon_line(&mut self) -> Vec<TmuxCommand> {
+        self.take_numbered()
+            .filter(|pending| !pending.expired)
+            .map(|pending| pending.command)
+            .collect()
+    }
+
+    /// Marks commands past their deadline as expired, returning them. They are
+    /// kept, so their blocks can still be told apart from those of other commands.
+    fn sweep_expired(&mut self) -> Vec<TmuxCommand> {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        let pending_commands = self
+            .numbered
+            .values_mut()
+            .chain(self.lines.iter_mut().flatten());
+        for pending in pending_commands {
+            if !pending.expired && pending.deadline <= now {
+                pending.expired = true;
+                expired.push(pending.command);
+            }
+        }
+        expired
+    }
+}
+
+/// Command whose output block has started, output is collected until the block ends
+struct InFlightCommand {
+    command: TmuxCommand,
+    output: Vec<Vec<u8>>,
+}
+
+/// Output block Tmux is currently sending, Tmux never interleaves them
+struct OpenBlock {
+    number: u32,
+    /// Unsolicited blocks and late replies are skipped
+    in_flight: Option<InFlightCommand>,
+}
+
 struct TmuxParserState {
-    ssh_target: Option<String>,
     event_channel: Sender<TmuxEvent>,
-    command_queue: Receiver<TmuxCommand>,
-    current_command: Option<TmuxCommand>,
-    is_error: bool,
-    result_line: usize,
-    empty_line_count: usize,
+    commands: Arc<Mutex<CommandTracker>>,
+    current_block: Option<OpenBlock>,
+    attached: bool,
+    /// Beginning of a line which has not been fully received yet
+    partial_line: Vec<u8>,
 }
 
 impl TmuxParserState {
-    fn new(
-        tmux_event_sender: Sender<TmuxEvent>,
-        cmd_queue_receiver: Receiver<TmuxCommand>,
-        ssh_target: Option<String>,
-    ) -> Self {
+    fn new(tmux_event_sender: Sender<TmuxEvent>, commands: Arc<Mutex<CommandTracker>>) -> Self {
         Self {
-            command_queue: cmd_queue_receiver,
+            commands,
             event_channel: tmux_event_sender,
-            current_command: None,
-            is_error: false,
-            ssh_target,
-            result_line: 0,
-            empty_line_count: 0,
+            current_block: None,
+            attached: false,
+            partial_line: Vec::new(),
         }
     }
 }
@@ -149,25 +502,39 @@ impl TmuxParserState {
 impl TmuxAPI {
     pub fn new(
         session_name: &str,
+        attach_mode: AttachMode,
         ssh_session: Option<SSHData>,
         window: &IvyTmuxWindow,
     ) -> Result<TmuxAPI, IvyError> {
         // Create async channels
         let (tmux_event_sender, tmux_event_receiver): (Sender<TmuxEvent>, Receiver<TmuxEvent>) =
-            async_channel::unbounded();
+            async_channel::bounded(EVENT_QUEUE_SIZE);
 
-        // Command queue
-        let (cmd_queue_sender, cmd_queue_receiver): (Sender<TmuxCommand>, Receiver<TmuxCommand>) =
-            async_channel::unbounded();
-        // Parse attach output
-        cmd_queue_sender.send_blocking(TmuxCommand::Init).unwrap();
+        // Editor opens remote paths using the SSH target
+        let ssh_target = ssh_session.as_ref().map(|ssh_data| ssh_data.0.clone());
+
+        // Commands waiting for their output
+        let commands = Arc::new(Mutex::new(CommandTracker::default()));
+        let sweep_sender = tmux_event_sender.clone();
+        let write_error_sender = tmux_event_sender.clone();
 
         // Spawn TMUX subprocess
         let spawn = if let Some(tuple) = ssh_session {
-            new_with_ssh(session_name, tuple, tmux_event_sender, cmd_queue_receiver)
+            new_with_ssh(
+                session_name,
+                attach_mode,
+                tuple,
+                tmux_event_sender,
+                commands.clone(),
+            )
         } else {
-            new_without_ssh(session_name, tmux_event_sender, cmd_queue_receiver)
-                .map(|ok| (ok, None))
+            new_without_ssh(
+                session_name,
+                attach_mode,
+                tmux_event_sender,
+                commands.clone(),
+            )
+            .map(|ok| (ok, None))
         };
         let (writer, ssh_session) = spawn?;
 
@@ -182,18 +549,48 @@ impl TmuxAPI {
             }
         ));
 
+        // Report commands Tmux did not answer in time, even while it is idle
+        let sweep_future = glib::spawn_future_local(glib::clone!(
+            #[strong]
+            commands,
+            async move {
+                loop {
+                    glib::timeout_future(COMMAND_SWEEP_INTERVAL).await;
+                    let expired = commands.lock().unwrap().sweep_expired();
+                    for command in expired {
+                        eprintln!("Tmux command {:?} timed out", command);
+                        let message = format!("{} timed out", command.description());
+                        for event in command_failed_events(&command, Some(message)) {
+                            if sweep_sender.send(event).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+        ));
+
         // Handle Tmux STDIN
         let tmux = TmuxAPI {
             ssh_session,
-            stdin_stream: RefCell::new(writer),
-            command_queue: cmd_queue_sender,
+            ssh_target,
+            writer: Rc::new(RefCell::new(TmuxWriter::new(writer))),
+            write_error_sender,
+            commands,
             window_size: Cell::new((0, 0)),
             resize_future: Cell::new(false),
+            pending_keys: RefCell::new(Vec::new()),
+            keys_in_flight: Cell::new(0),
             receive_future,
+            sweep_future,
         };
 
         Ok(tmux)
     }
+
+    pub fn ssh_target(&self) -> &Option<String> {
+        &self.ssh_target
+    }
 }
 
 #[inline]
@@ -206,25 +603,54 @@ fn read_into_ringbuffer<T: Read>(
     let write_buffer = ring_buffer.as_write_slice(len);
 
     // Read into byte array
-    stream.read(write_buffer).map(|bytes_read| {
+    stream.read(write_buffer).inspect(|&bytes_read| {
         if bytes_read > 0 {
             // Move the ringbuffer write position
             ring_buffer.feed(bytes_read);
         }
-
-        bytes_read
     })
 }
 
+/// Tmux arguments (following "-2 -C") which attach to or create the session
+fn attach_arguments(session_name: &str, attach_mode: AttachMode) -> Vec<String> {
+    match attach_mode {
+        // '=' prefix makes Tmux match the session name exactly
+        AttachMode::Attach => vec![
+            "attach-session".to_string(),
+            "-t".to_string(),
+            format!("={}", session_name),
+        ],
+        // Tmux picks a name if none was given
+        AttachMode::Create if session_name.is_empty() => vec!["new-session".to_string()],
+        AttachMode::Create => vec![
+            "new-session".to_string(),
+            "-s".to_string(),
+            session_name.to_string(),
+        ],
+    }
+}
+
+/// Quotes the argument for use in a POSIX shell
+fn shell_quote(argument: &str) -> String {
+    format!("'{}'", argument.replace('\'', "'\\''"))
+}
+
 fn new_with_ssh(
     tmux_name: &str,
+    attach_mode: AttachMode,
     ssh_data: SSHData,
     tmux_event_sender: Sender<TmuxEvent>,
-    cmd_queue_receiver: Receiver<TmuxCommand>,
+    commands: Arc<Mutex<CommandTracker>>,
 ) -> Result<(Box<dyn Write>, Option<Session>), IvyError> {
-    let SSHData(ssh_target, session, mut poll, mut events) = ssh_data;
-
-    let command = format!("tmux -2 -C new-session -A -s {}", tmux_name);
+    let SSHData(_, session, mut poll, mut events) = ssh_data;
+    // Used to report a dropped connection after the parser state is gone
+    let disconnect_sender = tmux_event_sender.clone();
+
+    let arguments: Vec<String> = attach_arguments(tmux_name, attach_mode)
+        .iter()
+        .map(|argument| shell_quote(argument))
+        .collect();
+    let command = format!("tmux -2 -C {}", arguments.join(" "));
     let mut channel = session.channel_session().unwrap();
     channel.exec(&command).map_err(|err| {
         eprintln!("channel.exec() failed with: {}", err);
@@ -237,8 +663,7 @@ fn new_with_ssh(
     let mut ssh_stderr = channel.stderr();
 
     spawn_blocking(move || {
-        let mut state =
-            TmuxParserState::new(tmux_event_sender, cmd_queue_receiver, Some(ssh_target));
+        let mut state = TmuxParserState::new(tmux_event_sender, commands);
         // Memory mapped ringbuffer appears contiguous to our program
         let mut ring_buffer = Ring::new(16_000).unwrap();
         let mut stderr_buffer = vec![0; 4096];
@@ -251,7 +676,9 @@ fn new_with_ssh(
                 match read_into_ringbuffer(&mut ssh_stdout, &mut ring_buffer) {
                     Ok(bytes_read) => {
                         if bytes_read < 1 {
-                            continue;
+                            // Remote Tmux ended without telling us why
+                            let _ = state.event_channel.send_blocking(TmuxEvent::Exit(None));
+                            return Err(TmuxError::ExitEventReceived);
                         }
 
                         let read_again = ring_buffer.is_full();
@@ -259,7 +686,7 @@ fn new_with_ssh(
                         // Consume the read bytes
                         tmux_parse_data(&mut state, &mut ring_buffer)?;
 
-                        if read_again == false {
+                        if !read_again {
                             break;
                         }
                     }
@@ -279,7 +706,7 @@ fn new_with_ssh(
                     let data = stderr_buffer[..bytes_read].to_vec();
                     let s = String::from_utf8(data).unwrap();
                     let mut stderr = stderr.lock();
-                    stderr.write(s.as_bytes()).unwrap();
+                    stderr.write_all(s.as_bytes()).unwrap();
                 }
                 Err(e) => {
                     if e.kind() != std::io::ErrorKind::WouldBlock {
@@ -292,17 +719,24 @@ fn new_with_ssh(
             Ok(())
         };
 
+        let disconnected = || {
+            debug!("SSH connection dropped");
+            let _ = disconnect_sender.send_blocking(TmuxEvent::Disconnected);
+        };
+
         loop {
-            if let Err(_) = poll.poll(&mut events, None) {
-                return;
+            if poll.poll(&mut events, None).is_err() {
+                return disconnected();
             }
 
             for event in events.iter() {
                 match event.token() {
                     SSH_TOKEN => {
                         if event.is_readable() {
-                            if let Err(_) = handle_event() {
-                                return;
+                            match handle_event() {
+                                Ok(_) => {}
+                                Err(TmuxError::SshClosed) => return disconnected(),
+                                Err(_) => return,
                             }
                         }
                     }
@@ -310,28 +744,26 @@ fn new_with_ssh(
                 }
 
                 if event.is_error() || event.is_read_closed() || event.is_write_closed() {
-                    return;
+                    return disconnected();
                 }
             }
         }
     });
 
-    return Ok((Box::new(ssh_stdin), Some(session)));
+    Ok((Box::new(ssh_stdin), Some(session)))
 }
 
 fn new_without_ssh(
     session_name: &str,
+    attach_mode: AttachMode,
     tmux_event_sender: Sender<TmuxEvent>,
-    cmd_queue_receiver: Receiver<TmuxCommand>,
+    commands: Arc<Mutex<CommandTracker>>,
 ) -> Result<Box<dyn Write>, IvyError> {
     println!("Attaching to Tmux session {}", session_name);
     let mut process = Command::new("tmux")
         .arg("-2")
         .arg("-C")
-        .arg("new-session")
-        .arg("-A")
-        .arg("-s")
-        .arg(session_name)
+        .args(attach_arguments(session_name, attach_mode))
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -340,27 +772,27 @@ fn new_without_ssh(
 
     // Read from Tmux STDOUT and send events to the channel on a separate thread
     let mut stdout_stream = process.stdout.take().expect("Failed to open stdout");
+    let stdin_stream = process.stdin.take().expect("Failed to open stdin");
     spawn_blocking(move || {
         let mut ring_buffer = Ring::new(16_000).unwrap();
-        let mut state = TmuxParserState::new(tmux_event_sender, cmd_queue_receiver, None);
+        let mut state = TmuxParserState::new(tmux_event_sender, commands);
 
-        loop {
-            match read_into_ringbuffer(&mut stdout_stream, &mut ring_buffer) {
-                Ok(bytes_read) => {
-                    if bytes_read < 1 {
-                        continue;
-                    }
+        while let Ok(bytes_read) = read_into_ringbuffer(&mut stdout_stream, &mut ring_buffer) {
+            if bytes_read < 1 {
+                // Tmux ended without telling us why
+                let _ = state.event_channel.send_blocking(TmuxEvent::Exit(None));
+                break;
+            }
 
-                    // Consume the read bytes
-                    if let Err(_) = tmux_parse_data(&mut state, &mut ring_buffer) {
-                        return;
-                    }
-                }
-                Err(_) => break,
+            // Consume the read bytes
+            if tmux_parse_data(&mut state, &mut ring_buffer).is_err() {
+                break;
             }
         }
+
+        // Reap Tmux once we stop reading its output
+        let _ = process.wait();
     });
 
-    let stdin_stream = process.stdin.take().expect("Failed to open stdin");
-    return Ok(Box::new(stdin_stream));
+    Ok(Box::new(stdin_stream))
 }
@@ -4,11 +4,13 @@ use enumflags2::{BitFlag, BitFlags};
 use gtk4::Orientation;
 use log::debug;
 
+use crate::helpers::TmuxError;
+
 use super::{LayoutFlags, LayoutSync, Rectangle, TmuxPane};
 
-pub fn parse_tmux_layout(buffer: &[u8]) -> LayoutSync {
-    // Example layout:
-    // @0 a705,80x31,0,0[80x15,0,0,0,80x15,0,16{40x15,0,16,1,39x15,41,16,2}] a85f,80x31,0,0,2
+pub fn parse_tmux_layout(buffer: &[u8]) -> Result<LayoutSync, TmuxError> {
+    // Example layout (index and name are missing in %layout-change):
+    // @0 a705,80x31,0,0[80x15,0,0,0,80x15,0,16{40x15,0,16,1,39x15,41,16,2}] a85f,80x31,0,0,2 * 0 name
     debug!("Given layout {}", from_utf8(buffer).unwrap());
 
     // Skip initial @, if it exists
@@ -16,21 +18,22 @@ pub fn parse_tmux_layout(buffer: &[u8]) -> LayoutSync {
         debug!("Skipping @");
         &buffer[1..]
     } else {
-        &buffer
+        buffer
     };
 
     // Read tab ID
-    let (tab_id, bytes_read) = read_first_u32(buffer);
+    let (tab_id, bytes_read) = read_first_u32(buffer)?;
     let buffer = &buffer[bytes_read + 1..];
 
     // Parse real layout
     let space_position = read_until_char(buffer, b' ');
-    let real_hierarchy = parse_layout_root(&buffer[..space_position]);
+    let real_hierarchy = parse_layout_root(&buffer[..space_position])?;
+    let window_layout = String::from_utf8_lossy(&buffer[..space_position]).to_string();
     let buffer = &buffer[space_position + 1..];
 
     // Parse visible layout
     let space_position = read_until_char(buffer, b' ');
-    let visible_hierarchy = parse_layout_root(&buffer[..space_position]);
+    let visible_hierarchy = parse_layout_root(&buffer[..space_position])?;
     let buffer = &buffer[space_position + 1..];
 
     // Parse window flags
@@ -38,32 +41,42 @@ pub fn parse_tmux_layout(buffer: &[u8]) -> LayoutSync {
     debug!("Flags {}", from_utf8(&buffer[..space_position]).unwrap());
     let flags = parse_flags(&buffer[..space_position]);
 
-    let name = if buffer.len() > space_position {
+    let (index, name) = if buffer.len() > space_position {
         let buffer = &buffer[space_position + 1..];
+        let (index, bytes_read) = read_first_u32(buffer)?;
+        let buffer = buffer.get(bytes_read + 1..).unwrap_or_default();
         let name = String::from_utf8(buffer.to_vec()).unwrap();
-        debug!("Found Tab name! {}", name);
-        Some(name)
+        debug!("Found Tab index {} and name! {}", index, name);
+        (Some(index), Some(name))
     } else {
-        None
+        (None, None)
     };
 
-    LayoutSync {
+    Ok(LayoutSync {
         tab_id,
         layout: real_hierarchy,
         visible_layout: visible_hierarchy,
         flags,
+        window_layout,
+        index,
         name,
-    }
+    })
 }
 
+/// Parses `window_flags`, e.g. "*Z" or "#!"
 #[inline]
-fn parse_flags(buffer: &[u8]) -> BitFlags<LayoutFlags> {
+pub fn parse_flags(buffer: &[u8]) -> BitFlags<LayoutFlags> {
     let mut flags = LayoutFlags::empty();
 
     for byte in buffer {
         match *byte {
             b'*' => flags |= LayoutFlags::HasFocus,
             b'Z' => flags |= LayoutFlags::IsZoomed,
+            b'-' => flags |= LayoutFlags::IsLast,
+            b'#' => flags |= LayoutFlags::Activity,
+            b'!' => flags |= LayoutFlags::Bell,
+            b'~' => flags |= LayoutFlags::Silence,
+            b'M' => flags |= LayoutFlags::Marked,
             _ => {}
         }
     }
@@ -72,7 +85,7 @@ fn parse_flags(buffer: &[u8]) -> BitFlags<LayoutFlags> {
 }
 
 #[inline]
-fn parse_layout_root(buffer: &[u8]) -> Vec<TmuxPane> {
+fn parse_layout_root(buffer: &[u8]) -> Result<Vec<TmuxPane>, TmuxError> {
     debug!("parse_layout_root layout {}", from_utf8(buffer).unwrap());
 
     // Skip the initial whatever
@@ -80,32 +93,31 @@ fn parse_layout_root(buffer: &[u8]) -> Vec<TmuxPane> {
     let buffer = &buffer[bytes_read + 1..];
 
     let mut hierarchy = Vec::new();
-    parse_layout_recursive(buffer, &mut hierarchy, 0);
+    parse_layout_recursive(buffer, &mut hierarchy)?;
 
-    hierarchy
+    Ok(hierarchy)
 }
 
-fn parse_layout_recursive(buffer: &[u8], hierarchy: &mut Vec<TmuxPane>, nested: u32) {
+fn parse_layout_recursive(buffer: &[u8], hierarchy: &mut Vec<TmuxPane>) -> Result<(), TmuxError> {
     // We can assume that layout is purse ASCII text
     let mut buffer = buffer;
 
     loop {
-        // print_tab(nested);
         debug!("Remaining buffer: {}", from_utf8(buffer).unwrap());
         // Read width
-        let (width, bytes_read) = read_first_u32(buffer);
+        let (width, bytes_read) = read_first_u32(buffer)?;
         buffer = &buffer[bytes_read + 1..];
 
         // Read height
-        let (height, bytes_read) = read_first_u32(buffer);
+        let (height, bytes_read) = read_first_u32(buffer)?;
         buffer = &buffer[bytes_read + 1..];
 
         // Read x coordinate
-        let (x, bytes_read) = read_first_u32(buffer);
+        let (x, bytes_read) = read_first_u32(buffer)?;
         buffer = &buffer[bytes_read + 1..];
 
         // Read y coordinate
-        let (y, bytes_read) = read_first_u32(buffer);
+        let (y, bytes_read) = read_first_u32(buffer)?;
         buffer = &buffer[bytes_read..];
 
         let allocation = Rectangle {
@@ -116,17 +128,17 @@ fn parse_layout_recursive(buffer: &[u8], hierarchy: &mut Vec<TmuxPane>, nested:
         };
 
         // Now we have to determine if this is a Pane or a Container
-        if buffer[0] == ',' as u8 {
+        if buffer[0] == b',' {
             // This is a Pane
             buffer = &buffer[1..];
 
-            let (pane_id, bytes_read) = read_first_u32(buffer);
+            let (pane_id, bytes_read) = read_first_u32(buffer)?;
             hierarchy.push(TmuxPane::Terminal(pane_id, allocation));
 
             buffer = &buffer[bytes_read..];
         } else {
             // This is a Container
-            let (orientation, open, close) = if buffer[0] == '[' as u8 {
+            let (orientation, open, close) = if buffer[0] == b'[' {
                 (Orientation::Vertical, b'[', b']')
             } else {
                 (Orientation::Horizontal, b'{', b'}')
@@ -135,7 +147,7 @@ fn parse_layout_recursive(buffer: &[u8], hierarchy: &mut Vec<TmuxPane>, nested:
 
             // recursively call parse_tmux_layout
             let bytes_read = find_closing_bracket(buffer, open, close);
-            parse_layout_recursive(&buffer[1..bytes_read], hierarchy, nested + 1);
+            parse_layout_recursive(&buffer[1..bytes_read], hierarchy)?;
             hierarchy.push(TmuxPane::Return);
 
             buffer = &buffer[bytes_read + 1..];
@@ -147,20 +159,24 @@ fn parse_layout_recursive(buffer: &[u8], hierarchy: &mut Vec<TmuxPane>, nested:
 
         buffer = &buffer[1..];
     }
+
+    Ok(())
 }
 
 #[inline]
-pub fn read_first_u32(buffer: &[u8]) -> (u32, usize) {
+pub fn read_first_u32(buffer: &[u8]) -> Result<(u32, usize), TmuxError> {
     let mut i = 0;
     let mut number: u32 = 0;
 
     // Read buffer char by char (assuming ASCII) and parse number
     while i < buffer.len() && buffer[i] > 47 && buffer[i] < 58 {
-        number *= 10;
-        number += (buffer[i] - 48) as u32;
+        number = number
+            .checked_mul(10)
+            .and_then(|number| number.checked_add((buffer[i] - 48) as u32))
+            .ok_or(TmuxError::MalformedOutput)?;
         i += 1;
     }
-    (number, i)
+    Ok((number, i))
 }
 
 #[inline]
@@ -1,45 +1,83 @@
-use std::{
-    io::{self, BufRead, Write},
-    str::from_utf8,
-};
+use std::{io::BufRead, str::from_utf8};
 
 use async_channel::Sender;
 use log::debug;
 use vmap::io::Ring;
 
 use crate::{
-    helpers::{open_editor, TmuxError},
-    tmux_api::TmuxEvent,
+    helpers::TmuxError,
+    tmux_api::{
+        HistoryCapture, HistoryPart, PaneProperty, TmuxEvent, WINDOW_FLAGS_SUBSCRIPTION,
+        WINDOW_INDEX_SUBSCRIPTION, WINDOW_SYNC_SUBSCRIPTION,
+    },
+};
+
+use super::{
+    parse_layout::{parse_flags, parse_tmux_layout},
+    sessions::parse_session,
+    InFlightCommand, OpenBlock, TmuxCommand, TmuxParserState,
 };
 
-use super::{parse_layout::parse_tmux_layout, TmuxCommand, TmuxParserState};
+/// DECSET modes matching the flags in `MOUSE_MODE_FORMAT` (send.rs)
+const MOUSE_MODES: [u32; 5] = [1000, 1002, 1003, 1005, 1006];
 
 pub fn tmux_parse_data(
     state: &mut TmuxParserState,
     ring_buffer: &mut Ring,
 ) -> Result<(), TmuxError> {
-    let mut consumed_bytes = 0;
     let buffer = ring_buffer.as_ref();
+    let buffer_len = buffer.len();
+    let mut line_start = 0;
 
     for (i, b) in buffer.iter().enumerate() {
         if *b == b'\n' {
-            let buffer = &buffer[consumed_bytes..i];
-            consumed_bytes += tmux_parse_line(state, buffer)? + 1; // +1 to account for \n
+            let line = &buffer[line_start..i];
+            line_start = i + 1;
+
+            if state.partial_line.is_empty() {
+                tmux_parse_line_checked(state, line)?;
+            } else {
+                // Beginning of this line was received in a previous read
+                let mut partial_line = std::mem::take(&mut state.partial_line);
+                partial_line.extend_from_slice(line);
+                tmux_parse_line_checked(state, &partial_line)?;
+
+                // Reuse the allocation for the next long line
+                partial_line.clear();
+                state.partial_line = partial_line;
+            }
         }
     }
 
-    // Move the ringbuffer read position
-    ring_buffer.consume(consumed_bytes);
+    // Line may be longer than the ringbuffer, so we keep the incomplete line
+    // ourselves and always consume everything
+    state.partial_line.extend_from_slice(&buffer[line_start..]);
+    ring_buffer.consume(buffer_len);
 
     Ok(())
 }
 
+/// Parses a single line, skipping it if it contains malformed data
+#[inline]
+fn tmux_parse_line_checked(state: &mut TmuxParserState, buffer: &[u8]) -> Result<(), TmuxError> {
+    match tmux_parse_line(state, buffer) {
+        Err(err) if !err.is_fatal() => {
+            eprintln!(
+                "Skipping malformed Tmux output ({:?}): {}",
+                err,
+                String::from_utf8_lossy(buffer)
+            );
+            Ok(())
+        }
+        result => result,
+    }
+}
+
 /// Parses Tmux output and replaces octal escapes sequences with correct binary
 /// characters
 #[inline]
 fn parse_escaped_output(input: &[u8], prepend_linebreak: bool, empty_lines: usize) -> Vec<u8> {
-    let input_len = input.len();
-    let mut output = Vec::with_capacity(input_len + (empty_lines * 2) + 3);
+    let mut output = Vec::with_capacity(input.len() + (empty_lines * 2) + 3);
 
     if prepend_linebreak {
         output.push(b'\r');
@@ -51,43 +89,46 @@ fn parse_escaped_output(input: &[u8], prepend_linebreak: bool, empty_lines: usiz
         output.push(b'\n');
     }
 
+    decode_escaped(input, &mut output);
+    output
+}
+
+/// Appends decoded `input` to `output`. Tmux escapes characters as a backslash
+/// followed by exactly 3 octal digits (e.g. \033), and backslash itself as \\.
+/// Malformed escapes are passed through unchanged, instead of losing the line.
+fn decode_escaped(input: &[u8], output: &mut Vec<u8>) {
     let mut i = 0;
-    while i < input_len {
-        let char = input[i];
-        if char == b'\\' {
-            // First \ is an escape, meaning there is 100% another char after it
-            if input[i + 1] == b'\\' {
-                // First \ is followed by another \
+    while i < input.len() {
+        let byte = input[i];
+        if byte == b'\\' {
+            if input.get(i + 1) == Some(&b'\\') {
                 output.push(b'\\');
                 i += 2;
                 continue;
             }
-
-            // Maybe an escape sequence?
-            if i + 3 >= input_len {
-                panic!("Found escape character but string too short");
+            if let Some(byte) = input.get(i + 1..i + 4).and_then(octal_value) {
+                output.push(byte);
+                i += 4;
+                continue;
             }
+        }
 
-            // This is an escape sequence
-            // TODO: This crashes when output is:
-            // tomc:~/dev/ivyterm/target/release (master) $ danes je pa tako M-\M-\
-            let mut ascii = 0;
-            for j in i + 1..i + 4 {
-                let num = input[j] - 48;
-                ascii *= 8;
-                ascii += num;
-            }
-            output.push(ascii);
+        output.push(byte);
+        i += 1;
+    }
+}
 
-            // We also read 3 extra characters after \
-            i += 4;
-        } else {
-            output.push(char);
-            i += 1;
+#[inline]
+fn octal_value(digits: &[u8]) -> Option<u8> {
+    let mut value: u16 = 0;
+    for digit in digits {
+        match digit {
+            b'0'..=b'7' => value = value * 8 + (digit - b'0') as u16,
+            _ => return None,
         }
     }
-
-    output
+    // Three octal digits can encode values up to 511
+    u8::try_from(value).ok()
 }
 
 #[inline]
@@ -115,153 +156,435 @@ fn parse_utf8(buffer: &[u8]) -> Result<&str, TmuxError> {
 }
 
 #[inline]
-pub fn tmux_parse_line(state: &mut TmuxParserState, buffer: &[u8]) -> Result<usize, TmuxError> {
-    let event_channel = &mut state.event_channel;
-    let command_queue = &mut state.command_queue;
-
-    // TODO: Handle output larger than 65534 bytes
-    // All output from Tmux is ASCII, except %output which we handle separately
-    if buffer.len() == 0 {
-        return Ok(0);
-    }
+pub fn tmux_parse_line(state: &mut TmuxParserState, buffer: &[u8]) -> Result<(), TmuxError> {
+    // Inside a block, everything up until the matching %end or %error is output of
+    // the command, even if it happens to start with %
+    if let Some(block) = &mut state.current_block {
+        if buffer_starts_with(buffer, "%end ") || buffer_starts_with(buffer, "%error ") {
+            // Output line which merely looks like a guard is still output
+            let end_number = parse_block_guard(buffer).map(|(end_number, _)| end_number);
+            if end_number.is_ok_and(|end_number| end_number == block.number) {
+                let is_error = buffer_starts_with(buffer, "%error");
+                let block = state.current_block.take();
+
+                // Block might be unsolicited, in which case we have nothing to finish
+                if let Some(in_flight) = block.and_then(|block| block.in_flight) {
+                    if is_error {
+                        tmux_command_failed(state, in_flight)?;
+                    } else {
+                        tmux_command_finished(state, in_flight)?;
+                    }
+                }
 
-    debug!("Tmux output: .{}.", parse_utf8(&buffer)?);
+                return Ok(());
+            }
+        }
 
-    // If buffer is empty or does not start with %, then this is output from a
-    // command we executed
-    if buffer.is_empty() || buffer[0] != b'%' {
         // This is output from a command we ran
-        if state.is_error {
-            let stderr = io::stderr();
-            let mut stderr = stderr.lock();
-            stderr.write(buffer).unwrap();
-        } else if let Some(command) = &state.current_command {
-            tmux_command_result(
-                command,
-                buffer,
-                state.result_line,
-                state.empty_line_count,
-                &event_channel,
-                &state.ssh_target,
-            )?;
+        if let Some(in_flight) = &mut block.in_flight {
+            in_flight.output.push(buffer.to_vec());
         }
 
-        state.result_line += 1;
-        state.empty_line_count = 0;
+        return Ok(());
+    }
+
+    let event_channel = &mut state.event_channel;
 
-        return Ok(buffer.len());
+    // All output from Tmux is ASCII, except %output which we handle separately
+    if buffer.is_empty() {
+        return Ok(());
+    }
+
+    debug!("Tmux output: .{}.", String::from_utf8_lossy(buffer));
+
+    // Output outside of a block should not happen
+    if buffer[0] != b'%' {
+        debug!(
+            "Tmux output outside of a command block: {}",
+            parse_utf8(buffer)?
+        );
+        return Ok(());
     }
 
     // This is a notification
-    if buffer_starts_with(&buffer, "%output") {
+    if buffer_starts_with(buffer, "%output") {
         // We were given output, we can assume that up until pane_id, output is ASCII
-        let (pane_id, chars_read) = read_first_u32(&buffer[9..]);
-        let output = parse_escaped_output(&buffer[9 + chars_read..], false, 0);
-
-        receive_event(&event_channel, TmuxEvent::Output(pane_id, output, false))?;
-    } else if buffer_starts_with(&buffer, "%begin") {
-        // Beginning of output from a command we executed
-        state.current_command = Some(command_queue.recv_blocking().unwrap());
-    } else if buffer_starts_with(&buffer, "%end") {
-        // End of output from a command we executed
-        if let Some(current_command) = &state.current_command {
-            match current_command {
-                TmuxCommand::InitialOutput(pane_id) => {
-                    let pane_id = *pane_id;
-                    receive_event(
-                        &event_channel,
-                        TmuxEvent::ScrollOutput(pane_id, state.empty_line_count),
-                    )?;
-                    receive_event(&event_channel, TmuxEvent::InitialOutputFinished(pane_id))?;
-                }
-                TmuxCommand::ChangeSize(_, _) => {
-                    receive_event(&event_channel, TmuxEvent::SizeChanged)?;
-                }
-                TmuxCommand::InitialLayout => {
-                    receive_event(&event_channel, TmuxEvent::InitialLayoutFinished)?;
-                }
-                TmuxCommand::ClearScrollback(term_id) => {
-                    receive_event(&event_channel, TmuxEvent::ScrollbackCleared(*term_id))?;
-                }
-                _ => {}
+        // %output %1 output
+        let buffer = buffer.get(9..).ok_or(TmuxError::MalformedOutput)?;
+        let (pane_id, chars_read) = read_first_u32(buffer)?;
+        let buffer = buffer.get(chars_read..).unwrap_or_default();
+        let output = parse_escaped_output(buffer, false, 0);
+
+        receive_event(event_channel, TmuxEvent::Output(pane_id, output, false))?;
+    } else if buffer_starts_with(buffer, "%extended-output") {
+        // With flow control enabled, we receive this instead of %output
+        // %extended-output %1 1234 : output
+        let buffer = buffer.get(18..).ok_or(TmuxError::MalformedOutput)?;
+        let (pane_id, chars_read) = read_first_u32(buffer)?;
+        let buffer = buffer.get(chars_read..).unwrap_or_default();
+        // Skip age and any additional fields, they are terminated by a colon
+        let colon = buffer
+            .iter()
+            .position(|b| *b == b':')
+            .unwrap_or(buffer.len());
+        let output = if colon + 2 < buffer.len() {
+            parse_escaped_output(&buffer[colon + 2..], false, 0)
+        } else {
+            Vec::new()
+        };
+
+        receive_event(event_channel, TmuxEvent::Output(pane_id, output, false))?;
+    } else if buffer_starts_with(buffer, "%pause") {
+        // %pause %1
+        let (pane_id, _) = read_first_u32(buffer.get(8..).ok_or(TmuxError::MalformedOutput)?)?;
+        debug!("Tmux event: Pane {} paused", pane_id);
+        receive_event(event_channel, TmuxEvent::PanePaused(pane_id))?;
+    } else if buffer_starts_with(buffer, "%continue") {
+        // %continue %1
+        // Pane only counts as caught up after its screen has been captured again
+        let (pane_id, _) = read_first_u32(buffer.get(11..).ok_or(TmuxError::MalformedOutput)?)?;
+        debug!("Tmux event: Pane {} continued", pane_id);
+    } else if buffer_starts_with(buffer, "%begin") {
+        // Beginning of output from a command
+        // %begin 1578920019 258 1
+        let (number, flags) = parse_block_guard(buffer)?;
+        let mut lost = Vec::new();
+        let command = if !state.attached {
+            // The very first block always belongs to the attach command
+            state.attached = true;
+            Some(TmuxCommand::Init)
+        } else {
+            // Flag is set on blocks of commands sent by us
+            let ours = flags & 1 == 1;
+            let mut commands = state.commands.lock().unwrap();
+            commands.begin_block(number, ours, &mut lost)
+        };
+
+        for command in lost {
+            eprintln!("Tmux never answered command {:?}", command);
+            let message = format!("{} failed: no reply from Tmux", command.description());
+            for event in command_failed_events(&command, Some(message)) {
+                receive_event(&state.event_channel, event)?;
             }
         }
 
-        state.current_command = None;
-        state.is_error = false;
-        state.result_line = 0;
-        state.empty_line_count = 0;
-    } else if buffer_starts_with(&buffer, "%error") {
-        // TODO: We still don't actually print the error
-        eprintln!("Error on command {:?}", state.current_command);
-
-        // Command we executed produced an error
-        state.current_command = None;
-        state.is_error = false;
-        state.result_line = 0;
-        state.empty_line_count = 0;
-    } else if buffer_starts_with(&buffer, "%window-pane-changed") {
+        if command.is_none() {
+            debug!("Tmux event: Unsolicited block {}", number);
+        }
+        let in_flight = command.map(|command| InFlightCommand {
+            command,
+            output: Vec::new(),
+        });
+        state.current_block = Some(OpenBlock { number, in_flight });
+    } else if buffer_starts_with(buffer, "%end") || buffer_starts_with(buffer, "%error") {
+        // Block has already been closed (or never opened)
+        debug!("Tmux event: Unmatched {}", parse_utf8(buffer)?);
+    } else if buffer_starts_with(buffer, "%window-pane-changed") {
         // %window-pane-changed @0 %10
-        let (tab_id, chars_read) = read_first_u32(&buffer[22..]);
-        let buffer = &buffer[22 + chars_read + 1..];
-        let (pane_id, _) = read_first_u32(buffer);
+        let buffer = buffer.get(22..).ok_or(TmuxError::MalformedOutput)?;
+        let (tab_id, chars_read) = read_first_u32(buffer)?;
+        let buffer = buffer
+            .get(chars_read + 1..)
+            .ok_or(TmuxError::MalformedOutput)?;
+        let (pane_id, _) = read_first_u32(buffer)?;
         debug!(
             "Tmux event: Window {} focus changed to pane {}",
             tab_id, pane_id
         );
-        receive_event(&event_channel, TmuxEvent::PaneFocusChanged(tab_id, pane_id))?;
-    } else if buffer_starts_with(&buffer, "%window-add") {
-        // TODO: Instead of asking for info when creating a new window, ask for info
-        // after receiving this notification
+        receive_event(event_channel, TmuxEvent::PaneFocusChanged(tab_id, pane_id))?;
+    } else if buffer_starts_with(buffer, "%window-add") {
         // %window-add @32
-    } else if buffer_starts_with(&buffer, "%session-window-changed") {
+        let (tab_id, _) = read_first_u32(buffer.get(13..).ok_or(TmuxError::MalformedOutput)?)?;
+        debug!("Tmux event: Tab {} added", tab_id);
+        receive_event(event_channel, TmuxEvent::TabAdded(tab_id))?;
+    } else if buffer_starts_with(buffer, "%window-close") {
+        // %window-close @32
+        let (tab_id, _) = read_first_u32(buffer.get(15..).ok_or(TmuxError::MalformedOutput)?)?;
+        debug!("Tmux event: Tab {} closed", tab_id);
+        receive_event(event_channel, TmuxEvent::TabClosed(tab_id))?;
+    } else if buffer_starts_with(buffer, "%session-window-changed") {
         // %session-window-changed $1 @1
-        let (session_id, chars_read) = read_first_u32(&buffer[25..]);
-        let buffer = &buffer[25 + chars_read + 1..];
-        let (tab_id, _) = read_first_u32(buffer);
+        let buffer = buffer.get(25..).ok_or(TmuxError::MalformedOutput)?;
+        let (session_id, chars_read) = read_first_u32(buffer)?;
+        let buffer = buffer
+            .get(chars_read + 1..)
+            .ok_or(TmuxError::MalformedOutput)?;
+        let (tab_id, _) = read_first_u32(buffer)?;
         debug!(
             "Tmux event: Session {} focus changed to window {}",
             session_id, tab_id
         );
-        receive_event(&event_channel, TmuxEvent::TabFocusChanged(tab_id))?;
-    } else if buffer_starts_with(&buffer, "%unlinked-window-close") {
+        receive_event(event_channel, TmuxEvent::TabFocusChanged(tab_id))?;
+    } else if buffer_starts_with(buffer, "%unlinked-window-add") {
+        // %unlinked-window-add @6
+        // Window might have been linked into our session as well, the layout query
+        // will only return it if that is the case
+        let (tab_id, _) = read_first_u32(buffer.get(22..).ok_or(TmuxError::MalformedOutput)?)?;
+        debug!("Tmux event: Unlinked Tab {} added", tab_id);
+        receive_event(event_channel, TmuxEvent::TabAdded(tab_id))?;
+    } else if buffer_starts_with(buffer, "%unlinked-window-close") {
         // %unlinked-window-close @6
-        let (tab_id, _) = read_first_u32(&buffer[24..]);
+        let (tab_id, _) = read_first_u32(buffer.get(24..).ok_or(TmuxError::MalformedOutput)?)?;
         debug!("Tmux event: Tab {} closed", tab_id);
-        receive_event(&event_channel, TmuxEvent::TabClosed(tab_id))?;
-    } else if buffer_starts_with(&buffer, "%layout-change") {
+        receive_event(event_channel, TmuxEvent::TabClosed(tab_id))?;
+    } else if buffer_starts_with(buffer, "%layout-change") {
         // Layout has changed
-        let layout_sync = parse_tmux_layout(&buffer[15..]);
-        receive_event(&event_channel, TmuxEvent::LayoutChanged(layout_sync))?;
-    } else if buffer_starts_with(&buffer, "%session-changed") {
+        let buffer = buffer.get(15..).ok_or(TmuxError::MalformedOutput)?;
+        let layout_sync = parse_tmux_layout(buffer)?;
+        receive_event(event_channel, TmuxEvent::LayoutChanged(layout_sync))?;
+    } else if buffer_starts_with(buffer, "%session-changed") {
         // Session has changed
-        let (id, bytes_read) = read_first_u32(&buffer[18..]);
-        let name = parse_utf8(&buffer[18 + bytes_read..])?.to_string();
+        // %session-changed $1 main
+        let buffer = buffer.get(18..).ok_or(TmuxError::MalformedOutput)?;
+        let (id, bytes_read) = read_first_u32(buffer)?;
+        let name = parse_utf8(&buffer[bytes_read..])?.to_string();
         debug!("Tmux event: Session changed ({}): {}", id, name);
 
-        receive_event(&event_channel, TmuxEvent::SessionChanged(id, name))?;
-    } else if buffer_starts_with(&buffer, "%window-renamed") {
-        // Session has changed
-        let (id, bytes_read) = read_first_u32(&buffer[17..]);
-        let name = parse_utf8(&buffer[17 + bytes_read..])?.to_string();
+        receive_event(event_channel, TmuxEvent::SessionChanged(id, name))?;
+    } else if buffer_starts_with(buffer, "%window-renamed") {
+        // Tab has been renamed
+        // %window-renamed @1 name
+        let buffer = buffer.get(17..).ok_or(TmuxError::MalformedOutput)?;
+        let (id, bytes_read) = read_first_u32(buffer)?;
+        let name = parse_utf8(&buffer[bytes_read..])?.to_string();
         debug!("Tmux event: Tab renamed ({}): {}", id, name);
 
-        receive_event(&event_channel, TmuxEvent::TabRenamed(id, name))?;
-    } else if buffer_starts_with(&buffer, "%exit") {
+        receive_event(event_channel, TmuxEvent::TabRenamed(id, name))?;
+    } else if buffer_starts_with(buffer, "%subscription-changed") {
+        // %subscription-changed pane-path $1 @2 0 %3 : /home/user
+        // %subscription-changed window-flags $1 @2 0 - : #!
+        let notification = parse_utf8(buffer.get(22..).ok_or(TmuxError::MalformedOutput)?)?;
+        let (fields, value) = notification
+            .split_once(" : ")
+            .ok_or(TmuxError::MalformedOutput)?;
+        let fields: Vec<&str> = fields.split(' ').collect();
+        let name = fields.first().copied().unwrap_or_default();
+        // Fields which do not apply to the subscription are "-"
+        let read_id = |index: usize, prefix: char| {
+            fields
+                .get(index)
+                .and_then(|field| field.strip_prefix(prefix))
+                .and_then(|id| id.parse::<u32>().ok())
+        };
+
+        let property = PaneProperty::from_subscription_name(name);
+        if name == WINDOW_FLAGS_SUBSCRIPTION {
+            let tab_id = read_id(2, '@').ok_or(TmuxError::MalformedOutput)?;
+            debug!("Tmux event: Tab {} flags are {}", tab_id, value);
+            let flags = parse_flags(value.as_bytes());
+            receive_event(event_channel, TmuxEvent::TabFlagsChanged(tab_id, flags))?;
+        } else if name == WINDOW_INDEX_SUBSCRIPTION {
+            let tab_id = read_id(2, '@').ok_or(TmuxError::MalformedOutput)?;
+            let index = value.parse().map_err(|_| TmuxError::MalformedOutput)?;
+            debug!("Tmux event: Tab {} index is {}", tab_id, index);
+            receive_event(event_channel, TmuxEvent::TabIndexChanged(tab_id, index))?;
+        } else if name == WINDOW_SYNC_SUBSCRIPTION {
+            let tab_id = read_id(2, '@').ok_or(TmuxError::MalformedOutput)?;
+            let synchronized = value == "1";
+            debug!(
+                "Tmux event: Tab {} synchronized panes {}",
+                tab_id, synchronized
+            );
+            let event = TmuxEvent::TabSynchronizeChanged(tab_id, synchronized);
+            receive_event(event_channel, event)?;
+        } else if let (Some(property), Some(pane_id)) = (property, read_id(4, '%')) {
+            debug!("Tmux event: Pane {} {:?} is {}", pane_id, property, value);
+            let event = TmuxEvent::PanePropertyChanged(pane_id, property, value.to_string());
+            receive_event(event_channel, event)?;
+        } else {
+            debug!("Tmux event: Unknown subscription: {}", notification);
+        }
+    } else if buffer_starts_with(buffer, "%exit") {
         // Tmux client has exited
-        let reason = parse_utf8(&buffer[5..])?;
+        // %exit server exited
+        let reason = parse_utf8(buffer.get(5..).unwrap_or_default())?.trim();
         debug!("Tmux event: Exit received, reason: {}", reason);
-        receive_event(&event_channel, TmuxEvent::Exit)?;
+        let reason = if reason.is_empty() {
+            None
+        } else {
+            Some(reason.to_string())
+        };
+        receive_event(event_channel, TmuxEvent::Exit(reason))?;
         // Stop receiving events
         return Err(TmuxError::ExitEventReceived);
-    } else if buffer_starts_with(&buffer, "%client-session-changed") {
+    } else if buffer_starts_with(buffer, "%client-session-changed") {
     } else {
         // Unsupported notification
-        let notification = parse_utf8(&buffer)?;
+        let notification = parse_utf8(buffer)?;
         debug!("Tmux event: Unknown notification: {}", notification)
     }
 
-    Ok(buffer.len())
+    Ok(())
+}
+
+/// Parses `%begin`, `%end` and `%error` lines, returning the command number and flags
+#[inline]
+fn parse_block_guard(buffer: &[u8]) -> Result<(u32, u32), TmuxError> {
+    // %end 1578920019 258 1
+    let mut fields = parse_utf8(buffer)?.split(' ').skip(2);
+    let mut next_number = || {
+        fields
+            .next()
+            .and_then(|field| field.parse::<u32>().ok())
+            .ok_or(TmuxError::ErrorParsingBlockGuard)
+    };
+
+    let number = next_number()?;
+    let flags = next_number()?;
+    Ok((number, flags))
+}
+
+fn tmux_command_finished(
+    state: &TmuxParserState,
+    in_flight: InFlightCommand,
+) -> Result<(), TmuxError> {
+    let event_channel = &state.event_channel;
+    let command = &in_flight.command;
+
+    match command {
+        TmuxCommand::InitialOutput(pane_id) => {
+            // Pane output received until now is already part of the capture
+            receive_event(event_channel, TmuxEvent::InitialOutputStarted(*pane_id))?;
+        }
+        TmuxCommand::PaneCatchUp(pane_id) => {
+            // Captured screen replaces whatever the Terminal is currently showing
+            let clear_screen = b"\x1b[H\x1b[2J".to_vec();
+            receive_event(
+                event_channel,
+                TmuxEvent::Output(*pane_id, clear_screen, true),
+            )?;
+        }
+        // Parts of the history are put together by the Terminal
+        TmuxCommand::PaneHistory(pane_id, part) => {
+            // Missing part makes the Terminal give up on this page
+            match history_capture(*part, &in_flight.output) {
+                Ok(capture) => receive_event(
+                    event_channel,
+                    TmuxEvent::PaneHistoryCaptured(*pane_id, capture),
+                )?,
+                Err(err) => eprintln!("Skipping malformed output of {:?}: {:?}", command, err),
+            }
+            if *part == HistoryPart::Cursor {
+                receive_event(event_channel, TmuxEvent::PaneHistoryLoaded(*pane_id))?;
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    // Empty lines are only handled once we reach the next non-empty line
+    let mut result_line = 0;
+    let mut empty_lines = 0;
+    for line in in_flight.output.iter() {
+        if line.is_empty() {
+            empty_lines += 1;
+            continue;
+        }
+
+        let result = tmux_command_result(command, line, result_line, empty_lines, event_channel);
+        // A malformed line should not prevent the rest of the output from being shown
+        match result {
+            Err(err) if err.is_fatal() => return Err(err),
+            Err(err) => eprintln!("Skipping malformed output of {:?}: {:?}", command, err),
+            Ok(_) => {}
+        }
+        result_line += 1;
+        empty_lines = 0;
+    }
+
+    match command {
+        TmuxCommand::InitialOutput(pane_id) => {
+            let pane_id = *pane_id;
+            receive_event(event_channel, TmuxEvent::ScrollOutput(pane_id, empty_lines))?;
+            receive_event(event_channel, TmuxEvent::InitialOutputFinished(pane_id))?;
+        }
+        TmuxCommand::ChangeSize(_, _) => {
+            receive_event(event_channel, TmuxEvent::SizeChanged)?;
+        }
+        TmuxCommand::InitialLayout => {
+            receive_event(event_channel, TmuxEvent::InitialLayoutFinished)?;
+        }
+        TmuxCommand::ClearScrollback(term_id) => {
+            receive_event(event_channel, TmuxEvent::ScrollbackCleared(*term_id))?;
+        }
+        TmuxCommand::PaneCursor(pane_id) => {
+            receive_event(event_channel, TmuxEvent::PaneCaughtUp(*pane_id))?;
+        }
+        TmuxCommand::PasteChunk(pane_id) => {
+            receive_event(event_channel, TmuxEvent::PasteChunkSent(*pane_id))?;
+        }
+        TmuxCommand::Keypress => {
+            receive_event(event_channel, TmuxEvent::KeypressSent)?;
+        }
+        TmuxCommand::SessionList => {
+            let sessions = in_flight
+                .output
+                .iter()
+                .filter_map(|line| parse_session(&String::from_utf8_lossy(line)))
+                .collect();
+            receive_event(event_channel, TmuxEvent::SessionList(sessions))?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn tmux_command_failed(
+    state: &TmuxParserState,
+    in_flight: InFlightCommand,
+) -> Result<(), TmuxError> {
+    // Output of a failed command is the error message itself
+    let error: Vec<String> = in_flight
+        .output
+        .iter()
+        .filter(|line| !line.is_empty())
+        .map(|line| String::from_utf8_lossy(line).to_string())
+        .collect();
+    let error = error.join("\n");
+    eprintln!("Error on command {:?}: {}", in_flight.command, error);
+
+    let message = if error.is_empty() {
+        format!("{} failed", in_flight.command.description())
+    } else {
+        format!("{} failed: {}", in_flight.command.description(), error)
+    };
+    let mut events = command_failed_events(&in_flight.command, Some(message));
+
+    // Tmux does not run the rest of the line, but the error was already shown
+    let abandoned = state.commands.lock().unwrap().abandon_line();
+    for command in abandoned {
+        debug!("Tmux did not run {:?}", command);
+        events.extend(command_failed_events(&command, None));
+    }
+
+    for event in events {
+        receive_event(&state.event_channel, event)?;
+    }
+    Ok(())
+}
+
+/// Events which tell the window that a command will not produce any output.
+/// Message is shown to the user, if given.
+pub fn command_failed_events(command: &TmuxCommand, message: Option<String>) -> Vec<TmuxEvent> {
+    let mut events = Vec::new();
+    if let Some(message) = message {
+        events.push(TmuxEvent::CommandFailed(message));
+    }
+
+    match command {
+        // Remaining chunks of a failed paste should not be sent
+        TmuxCommand::PasteChunk(pane_id) => events.push(TmuxEvent::PasteFailed(*pane_id)),
+        // Keys typed in the meantime should not wait forever
+        TmuxCommand::Keypress => events.push(TmuxEvent::KeypressSent),
+        // Terminal gives up on loading the history
+        TmuxCommand::PaneHistory(pane_id, HistoryPart::Size | HistoryPart::Cursor) => {
+            events.push(TmuxEvent::PaneHistoryLoaded(*pane_id))
+        }
+        _ => {}
+    }
+
+    events
 }
 
 #[inline]
@@ -271,43 +594,42 @@ fn tmux_command_result(
     result_line: usize,
     empty_lines: usize,
     event_channel: &Sender<TmuxEvent>,
-    ssh_target: &Option<String>,
 ) -> Result<(), TmuxError> {
     match command {
-        TmuxCommand::TabNew => {
-            let layout_sync = parse_tmux_layout(buffer);
-            receive_event(&event_channel, TmuxEvent::TabNew(layout_sync))?;
+        TmuxCommand::TabLayout(_) => {
+            let layout_sync = parse_tmux_layout(buffer)?;
+            receive_event(event_channel, TmuxEvent::TabNew(layout_sync))?;
         }
         TmuxCommand::InitialLayout => {
-            let layout_sync = parse_tmux_layout(buffer);
-            receive_event(&event_channel, TmuxEvent::InitialLayout(layout_sync))?;
+            let layout_sync = parse_tmux_layout(buffer)?;
+            receive_event(event_channel, TmuxEvent::InitialLayout(layout_sync))?;
         }
         TmuxCommand::InitialOutput(pane_id) => {
-            let output = parse_escaped_output(&buffer, result_line > 0, empty_lines);
-
-            // let mut escaped = String::with_capacity(output.len() * 2);
-            // for c in &output {
-            //     if c.is_ascii_control() {
-            //         let x = std::ascii::escape_default(*c);
-            //         for c in x {
-            //             escaped.push(c as char);
-            //         }
-            //     } else {
-            //         escaped.push(*c as char);
-            //     }
-            // }
-            // println!("Tmux initial output for pane {}: |{}|", pane_id, escaped);
-
-            receive_event(&event_channel, TmuxEvent::Output(*pane_id, output, true))?;
-        }
-        TmuxCommand::PaneCurrentPath(term_id) => {
-            let (pane_id, bytes_read) = read_first_u32(&buffer[7..]);
-            // Currently Tmux sends paths of all Terminals in the given Tab, so we need
-            // to filter manually
-            if pane_id == *term_id {
-                let path = parse_utf8(&buffer[7 + bytes_read..])?;
-                open_editor(path, ssh_target);
+            let output = parse_escaped_output(buffer, result_line > 0, empty_lines);
+            receive_event(event_channel, TmuxEvent::Output(*pane_id, output, true))?;
+        }
+        TmuxCommand::PaneCatchUp(pane_id) => {
+            let output = parse_escaped_output(buffer, result_line > 0, empty_lines);
+            receive_event(event_channel, TmuxEvent::Output(*pane_id, output, true))?;
+        }
+        TmuxCommand::PaneCursor(pane_id) => {
+            let output = cursor_position_output(buffer)?;
+            receive_event(event_channel, TmuxEvent::Output(*pane_id, output, true))?;
+        }
+        TmuxCommand::PaneHistorySize(pane_id) => {
+            let (size, _) = read_first_u32(buffer)?;
+            receive_event(event_channel, TmuxEvent::PaneHistorySize(*pane_id, size))?;
+        }
+        TmuxCommand::PaneMouseMode(pane_id) => {
+            // Turn VTE mouse tracking on or off to match the pane, VTE then
+            // generates mouse reports for us
+            let mut output = Vec::new();
+            let flags = parse_utf8(buffer)?.split(' ');
+            for (mode, flag) in MOUSE_MODES.iter().zip(flags) {
+                let set = if flag == "1" { 'h' } else { 'l' };
+                output.extend_from_slice(format!("\x1b[?{}{}", mode, set).as_bytes());
             }
+            receive_event(event_channel, TmuxEvent::Output(*pane_id, output, true))?;
         }
         _ => {}
     }
@@ -316,15 +638,49 @@ fn tmux_command_result(
 }
 
 #[inline]
-pub fn read_first_u32(buffer: &[u8]) -> (u32, usize) {
+/// Escape sequence which moves the cursor to the position reported by Tmux
+fn cursor_position_output(buffer: &[u8]) -> Result<Vec<u8>, TmuxError> {
+    // Tmux coordinates start at 0, while escape sequences start at 1
+    let (y, bytes_read) = read_first_u32(buffer)?;
+    let (x, _) = read_first_u32(&buffer[bytes_read..])?;
+    Ok(format!("\x1b[{};{}H", y as u64 + 1, x as u64 + 1).into_bytes())
+}
+
+fn history_capture(part: HistoryPart, output: &[Vec<u8>]) -> Result<HistoryCapture, TmuxError> {
+    let first_line = output.first().map(Vec::as_slice).unwrap_or_default();
+    let capture = match part {
+        HistoryPart::Size => {
+            let (size, bytes_read) = read_first_u32(first_line)?;
+            let (limit, _) = read_first_u32(first_line.get(bytes_read..).unwrap_or_default())?;
+            HistoryCapture::Size(size, limit)
+        }
+        HistoryPart::Cursor => HistoryCapture::Cursor(cursor_position_output(first_line)?),
+        HistoryPart::Older | HistoryPart::Recent | HistoryPart::Screen => {
+            // Empty lines are kept, the Terminal needs the exact number of lines
+            let mut lines = Vec::with_capacity(output.len());
+            for line in output {
+                let mut decoded = Vec::with_capacity(line.len());
+                decode_escaped(line, &mut decoded);
+                lines.push(decoded);
+            }
+            HistoryCapture::Lines(part, lines)
+        }
+    };
+    Ok(capture)
+}
+
+pub fn read_first_u32(buffer: &[u8]) -> Result<(u32, usize), TmuxError> {
     let mut i = 0;
     let mut number: u32 = 0;
 
     // Read buffer char by char (assuming ASCII) and parse number
     while i < buffer.len() && buffer[i] > 47 && buffer[i] < 58 {
-        number *= 10;
-        number += (buffer[i] - 48) as u32;
+        number = number
+            .checked_mul(10)
+            .and_then(|number| number.checked_add((buffer[i] - 48) as u32))
+            .ok_or(TmuxError::MalformedOutput)?;
         i += 1;
     }
-    (number, i + 1)
+    // Skip the separator following the number, if there is one
+    Ok((number, (i + 1).min(buffer.len())))
 }
@@ -0,0 +1,466 @@
+//! Replays recorded Tmux control mode transcripts through the parser, so it can
+//! be tested without a running Tmux server
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use async_channel::{Receiver, Sender};
+use gtk4::Orientation;
+use vmap::io::Ring;
+
+use super::{
+    read_into_ringbuffer, receive::tmux_parse_data, CommandTracker, HistoryCapture, HistoryPart,
+    LayoutFlags, PaneProperty, TmuxCommand, TmuxEvent, TmuxPane, TmuxParserState,
+};
+
+/// Transcripts are fed in small chunks, so lines get split between reads
+const CHUNK_SIZE: usize = 13;
+
+/// Feeds the transcript to the parser, as if `commands` were sent to Tmux (each
+/// on its own line) before it produced any output. Stops once the parser returns
+/// an error (e.g. on %exit).
+fn replay(transcript: &[u8], commands: Vec<TmuxCommand>) -> Vec<TmuxEvent> {
+    let lines = commands.into_iter().map(|command| vec![command]).collect();
+    replay_lines(transcript, lines)
+}
+
+/// Same as `replay()`, but commands of each line were sent on a single line
+fn replay_lines(transcript: &[u8], lines: Vec<Vec<TmuxCommand>>) -> Vec<TmuxEvent> {
+    let mut commands = CommandTracker::default();
+    for line in lines {
+        commands.push_line(line);
+    }
+    replay_tracked(transcript, commands)
+}
+
+fn replay_tracked(transcript: &[u8], commands: CommandTracker) -> Vec<TmuxEvent> {
+    let (event_sender, event_receiver): (Sender<TmuxEvent>, Receiver<TmuxEvent>) =
+        async_channel::unbounded();
+    let mut state = TmuxParserState::new(event_sender, Arc::new(Mutex::new(commands)));
+    let mut ring_buffer = Ring::new(16_000).unwrap();
+    for mut chunk in transcript.chunks(CHUNK_SIZE) {
+        read_into_ringbuffer(&mut chunk, &mut ring_buffer).unwrap();
+        if tmux_parse_data(&mut state, &mut ring_buffer).is_err() {
+            break;
+        }
+    }
+
+    let mut events = Vec::new();
+    while let Ok(event) = event_receiver.try_recv() {
+        events.push(event);
+    }
+    events
+}
+
+/// Short description of a layout, e.g. "H(80x24,0,0) T0(40x24,0,0) T4(39x24,41,0) R"
+fn describe_layout(layout: &[TmuxPane]) -> String {
+    let describe = |pane: &TmuxPane| match pane {
+        TmuxPane::Terminal(id, r) => {
+            format!("T{}({}x{},{},{})", id, r.width, r.height, r.x, r.y)
+        }
+        TmuxPane::Container(orientation, r) => {
+            let kind = if *orientation == Orientation::Vertical {
+                "V"
+            } else {
+                "H"
+            };
+            format!("{}({}x{},{},{})", kind, r.width, r.height, r.x, r.y)
+        }
+        TmuxPane::Return => String::from("R"),
+    };
+
+    layout.iter().map(describe).collect::<Vec<_>>().join(" ")
+}
+
+#[test]
+fn initial_layout() {
+    let transcript = include_bytes!("fixtures/initial_layout.txt");
+    let events = replay(transcript, vec![TmuxCommand::InitialLayout]);
+    assert_eq!(events.len(), 6, "{:#?}", events);
+
+    match &events[0] {
+        TmuxEvent::SessionChanged(1, name) => assert_eq!(name, "main"),
+        event => panic!("Unexpected event {:?}", event),
+    }
+    match &events[1] {
+        TmuxEvent::InitialLayout(layout_sync) => {
+            assert_eq!(layout_sync.tab_id, 0);
+            assert_eq!(
+                describe_layout(&layout_sync.layout),
+                "V(80x31,0,0) T0(80x15,0,0) H(80x15,0,16) T1(40x15,0,16) T2(39x15,41,16) R R"
+            );
+            assert_eq!(
+                describe_layout(&layout_sync.visible_layout),
+                describe_layout(&layout_sync.layout)
+            );
+            assert_eq!(layout_sync.flags, LayoutFlags::HasFocus);
+            assert_eq!(
+                layout_sync.window_layout,
+                "a705,80x31,0,0[80x15,0,0,0,80x15,0,16{40x15,0,16,1,39x15,41,16,2}]"
+            );
+            assert_eq!(layout_sync.index, Some(0));
+            assert_eq!(layout_sync.name.as_deref(), Some("editor"));
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+    match &events[2] {
+        TmuxEvent::InitialLayout(layout_sync) => {
+            assert_eq!(layout_sync.tab_id, 1);
+            assert_eq!(describe_layout(&layout_sync.layout), "T3(80x31,0,0)");
+            assert_eq!(layout_sync.flags, LayoutFlags::IsLast);
+            assert_eq!(layout_sync.index, Some(1));
+            assert_eq!(layout_sync.name.as_deref(), Some("build logs"));
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+    assert!(matches!(events[3], TmuxEvent::InitialLayoutFinished));
+    assert!(matches!(events[4], TmuxEvent::PaneFocusChanged(0, 1)));
+    assert!(matches!(events[5], TmuxEvent::TabFocusChanged(1)));
+}
+
+#[test]
+fn layout_change() {
+    let transcript = include_bytes!("fixtures/layout_change.txt");
+    let events = replay(transcript, vec![TmuxCommand::PaneSplit(false)]);
+    assert_eq!(events.len(), 3, "{:#?}", events);
+
+    match &events[1] {
+        TmuxEvent::LayoutChanged(layout_sync) => {
+            assert_eq!(layout_sync.tab_id, 0);
+            assert_eq!(
+                describe_layout(&layout_sync.layout),
+                "H(80x24,0,0) T0(40x24,0,0) T4(39x24,41,0) R"
+            );
+            assert_eq!(layout_sync.flags, LayoutFlags::HasFocus);
+            assert_eq!(layout_sync.index, None);
+            assert_eq!(layout_sync.name, None);
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+    assert!(matches!(events[2], TmuxEvent::PaneFocusChanged(0, 4)));
+}
+
+#[test]
+fn zoom() {
+    let transcript = include_bytes!("fixtures/zoom.txt");
+    let commands = vec![TmuxCommand::PaneZoom(4), TmuxCommand::PaneZoom(4)];
+    let events = replay(transcript, commands);
+    assert_eq!(events.len(), 3, "{:#?}", events);
+
+    // Zoomed pane takes up the whole visible layout
+    match &events[1] {
+        TmuxEvent::LayoutChanged(layout_sync) => {
+            assert_eq!(
+                describe_layout(&layout_sync.layout),
+                "H(80x24,0,0) T0(40x24,0,0) T4(39x24,41,0) R"
+            );
+            assert_eq!(
+                describe_layout(&layout_sync.visible_layout),
+                "T4(80x24,0,0)"
+            );
+            assert_eq!(
+                layout_sync.flags,
+                LayoutFlags::HasFocus | LayoutFlags::IsZoomed
+            );
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+    // Unzooming restores the visible layout
+    match &events[2] {
+        TmuxEvent::LayoutChanged(layout_sync) => {
+            assert_eq!(
+                describe_layout(&layout_sync.visible_layout),
+                "H(80x24,0,0) T0(40x24,0,0) T4(39x24,41,0) R"
+            );
+            assert_eq!(layout_sync.flags, LayoutFlags::HasFocus);
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn renames() {
+    let transcript = include_bytes!("fixtures/renames.txt");
+    let commands = vec![TmuxCommand::TabRename(0), TmuxCommand::TabRename(9)];
+    let events = replay(transcript, commands);
+    assert_eq!(events.len(), 4, "{:#?}", events);
+
+    match &events[1] {
+        TmuxEvent::TabRenamed(0, name) => assert_eq!(name, "build output"),
+        event => panic!("Unexpected event {:?}", event),
+    }
+    match &events[2] {
+        TmuxEvent::TabRenamed(1, name) => assert_eq!(name, "logs"),
+        event => panic!("Unexpected event {:?}", event),
+    }
+    match &events[3] {
+        TmuxEvent::CommandFailed(message) => {
+            assert_eq!(message, "Renaming tab failed: can't find window: @9")
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn initial_output() {
+    let transcript = include_bytes!("fixtures/initial_output.txt");
+    let events = replay(transcript, vec![TmuxCommand::InitialOutput(0)]);
+    assert_eq!(events.len(), 8, "{:#?}", events);
+
+    // Terminal drops output queued before the capture
+    assert!(matches!(events[1], TmuxEvent::InitialOutputStarted(0)));
+    // Lines starting with % are output too, unless they end our block
+    let expected: [&[u8]; 3] = [
+        b"\x1b[1mbold\x1b[0m",
+        b"\r\n%end 1700000000 100 0",
+        b"\r\nC:\\Users",
+    ];
+    for (event, expected) in events[2..5].iter().zip(expected) {
+        match event {
+            TmuxEvent::Output(0, output, true) => assert_eq!(output.as_slice(), expected),
+            event => panic!("Unexpected event {:?}", event),
+        }
+    }
+    // Trailing empty line is reported separately
+    assert!(matches!(events[5], TmuxEvent::ScrollOutput(0, 1)));
+    assert!(matches!(events[6], TmuxEvent::InitialOutputFinished(0)));
+    match &events[7] {
+        TmuxEvent::Output(0, output, false) => assert_eq!(output.as_slice(), b"after\r\n"),
+        event => panic!("Unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn exit() {
+    let transcript = include_bytes!("fixtures/exit.txt");
+    let events = replay(transcript, vec![]);
+    // Nothing after %exit is parsed
+    assert_eq!(events.len(), 3, "{:#?}", events);
+
+    match &events[1] {
+        TmuxEvent::Output(0, output, false) => assert_eq!(output.as_slice(), b"bye\r\n"),
+        event => panic!("Unexpected event {:?}", event),
+    }
+    match &events[2] {
+        TmuxEvent::Exit(reason) => assert_eq!(reason.as_deref(), Some("server exited")),
+        event => panic!("Unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn malformed_output() {
+    let transcript = include_bytes!("fixtures/malformed_output.txt");
+    let events = replay(transcript, vec![TmuxCommand::InitialOutput(0)]);
+    assert_eq!(events.len(), 13, "{:#?}", events);
+
+    // Truncated and overflowing notifications are skipped, while malformed escapes are passed
+    // through unchanged instead of losing the line
+    let expected: [&[u8]; 6] = [
+        b"ok\r\n",
+        b"danes je pa tako M-\\M-\\",
+        b"trailing\\",
+        b"\\08",
+        b"\\777",
+        b"C:\\Users \\ \xff",
+    ];
+    for (event, expected) in events[1..7].iter().zip(expected) {
+        match event {
+            TmuxEvent::Output(0, output, false) => assert_eq!(output.as_slice(), expected),
+            event => panic!("Unexpected event {:?}", event),
+        }
+    }
+    assert!(matches!(events[7], TmuxEvent::InitialOutputStarted(0)));
+    let expected: [&[u8]; 3] = [b"\x1b[1mfine", b"\r\nbroken\\0", b"\r\n%end of output"];
+    for (event, expected) in events[8..11].iter().zip(expected) {
+        match event {
+            TmuxEvent::Output(0, output, true) => assert_eq!(output.as_slice(), expected),
+            event => panic!("Unexpected event {:?}", event),
+        }
+    }
+    assert!(matches!(events[11], TmuxEvent::ScrollOutput(0, 0)));
+    assert!(matches!(events[12], TmuxEvent::InitialOutputFinished(0)));
+}
+
+#[test]
+fn line_longer_than_ringbuffer() {
+    let long_output = "x".repeat(40_000);
+    let transcript = format!(
+        "%begin 1700000000 100 0\n%end 1700000000 100 0\n%output %0 {}\n%output %0 after\n",
+        long_output
+    );
+    let events = replay(transcript.as_bytes(), vec![]);
+    assert_eq!(events.len(), 2, "{} events", events.len());
+
+    match &events[0] {
+        TmuxEvent::Output(0, output, false) => assert_eq!(output, long_output.as_bytes()),
+        event => panic!("Unexpected event {:?}", event),
+    }
+    match &events[1] {
+        TmuxEvent::Output(0, output, false) => assert_eq!(output.as_slice(), b"after"),
+        event => panic!("Unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn pane_history() {
+    let transcript = "%begin 1700000000 100 0\n%end 1700000000 100 0\n\
+        %begin 1700000000 101 1\n4200 50000\n%end 1700000000 101 1\n\
+        %begin 1700000000 102 1\nolder\n\\033[1mbold\n%end 1700000000 102 1\n\
+        %begin 1700000000 103 1\n$ \n\n%end 1700000000 103 1\n\
+        %begin 1700000000 104 1\n0 2\n%end 1700000000 104 1\n";
+    let line = [
+        HistoryPart::Size,
+        HistoryPart::Older,
+        HistoryPart::Screen,
+        HistoryPart::Cursor,
+    ];
+    let line = line.map(|part| TmuxCommand::PaneHistory(0, part)).to_vec();
+    let events = replay_lines(transcript.as_bytes(), vec![line]);
+    assert_eq!(events.len(), 5, "{:#?}", events);
+
+    assert!(matches!(
+        events[0],
+        TmuxEvent::PaneHistoryCaptured(0, HistoryCapture::Size(4200, 50000))
+    ));
+    // Lines are decoded, and empty ones are kept
+    match &events[1] {
+        TmuxEvent::PaneHistoryCaptured(0, HistoryCapture::Lines(HistoryPart::Older, lines)) => {
+            assert_eq!(lines, &[b"older".to_vec(), b"\x1b[1mbold".to_vec()])
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+    match &events[2] {
+        TmuxEvent::PaneHistoryCaptured(0, HistoryCapture::Lines(HistoryPart::Screen, lines)) => {
+            assert_eq!(lines, &[b"$ ".to_vec(), Vec::new()])
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+    match &events[3] {
+        TmuxEvent::PaneHistoryCaptured(0, HistoryCapture::Cursor(cursor)) => {
+            assert_eq!(cursor.as_slice(), b"\x1b[1;3H")
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+    assert!(matches!(events[4], TmuxEvent::PaneHistoryLoaded(0)));
+}
+
+#[test]
+fn subscriptions() {
+    let transcript = "%begin 1700000000 100 0\n%end 1700000000 100 0\n\
+        %subscription-changed pane-path $1 @2 0 %3 : /home/user/my project\n\
+        %subscription-changed pane-title $1 @2 0 %3 : \n\
+        %subscription-changed unknown $1 @2 0 %3 : value\n\
+        %subscription-changed window-flags $1 @2 0 - : -#!~\n\
+        %subscription-changed window-index $1 @2 4 - : 4\n\
+        %subscription-changed window-sync $1 @2 4 - : 1\n\
+        %subscription-changed window-sync $1 @2 4 - : 0\n";
+    let events = replay(transcript.as_bytes(), vec![]);
+    assert_eq!(events.len(), 6, "{:#?}", events);
+
+    // Values may contain spaces or be empty
+    match &events[0] {
+        TmuxEvent::PanePropertyChanged(3, PaneProperty::CurrentPath, path) => {
+            assert_eq!(path, "/home/user/my project")
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+    match &events[1] {
+        TmuxEvent::PanePropertyChanged(3, PaneProperty::Title, title) => assert_eq!(title, ""),
+        event => panic!("Unexpected event {:?}", event),
+    }
+    match &events[2] {
+        TmuxEvent::TabFlagsChanged(2, flags) => assert_eq!(
+            *flags,
+            LayoutFlags::IsLast | LayoutFlags::Activity | LayoutFlags::Bell | LayoutFlags::Silence
+        ),
+        event => panic!("Unexpected event {:?}", event),
+    }
+    assert!(matches!(events[3], TmuxEvent::TabIndexChanged(2, 4)));
+    assert!(matches!(
+        events[4],
+        TmuxEvent::TabSynchronizeChanged(2, true)
+    ));
+    assert!(matches!(
+        events[5],
+        TmuxEvent::TabSynchronizeChanged(2, false)
+    ));
+}
+
+#[test]
+fn command_numbers() {
+    // Hook runs in between the commands of the first line, while the second line
+    // fails to parse, so its other command never gets a block. Same happens once
+    // a command on the third line fails.
+    let transcript = "%begin 1700000000 100 0\n%end 1700000000 100 0\n\
+        %begin 1700000000 105 1\n%end 1700000000 105 1\n\
+        %begin 1700000000 106 0\nhooked\n%end 1700000000 106 0\n\
+        %begin 1700000000 107 1\n7\n%end 1700000000 107 1\n\
+        %begin 1700000000 109 1\nparse error: unknown command: bogus\n%error 1700000000 109 1\n\
+        %begin 1700000000 112 1\ncan't find pane: %9\n%error 1700000000 112 1\n\
+        %begin 1700000000 115 1\n42\n%end 1700000000 115 1\n";
+    let lines = vec![
+        vec![TmuxCommand::TabRename(0), TmuxCommand::PaneHistorySize(1)],
+        vec![TmuxCommand::PasteChunk(2), TmuxCommand::PaneHistorySize(2)],
+        vec![TmuxCommand::PaneZoom(9), TmuxCommand::PasteChunk(9)],
+        vec![TmuxCommand::PaneHistorySize(3)],
+    ];
+    let events = replay_lines(transcript.as_bytes(), lines);
+    assert_eq!(events.len(), 6, "{:#?}", events);
+
+    assert!(matches!(events[0], TmuxEvent::PaneHistorySize(1, 7)));
+    match &events[1] {
+        TmuxEvent::CommandFailed(message) => assert_eq!(
+            message,
+            "Copying clipboard to Tmux failed: parse error: unknown command: bogus"
+        ),
+        event => panic!("Unexpected event {:?}", event),
+    }
+    assert!(matches!(events[2], TmuxEvent::PasteFailed(2)));
+    // Paste which was never run is cancelled, without another message
+    match &events[3] {
+        TmuxEvent::CommandFailed(message) => {
+            assert_eq!(message, "Zooming pane failed: can't find pane: %9")
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+    assert!(matches!(events[4], TmuxEvent::PasteFailed(9)));
+    assert!(matches!(events[5], TmuxEvent::PaneHistorySize(3, 42)));
+}
+
+#[test]
+fn keypress_answers() {
+    // Keys typed in the meantime are sent once Tmux answers, even with an error
+    let transcript = "%begin 1700000000 100 0\n%end 1700000000 100 0\n\
+        %begin 1700000000 101 1\n%end 1700000000 101 1\n\
+        %begin 1700000000 102 1\ncan't find pane: %9\n%error 1700000000 102 1\n";
+    let commands = vec![TmuxCommand::Keypress, TmuxCommand::Keypress];
+    let events = replay(transcript.as_bytes(), commands);
+    assert_eq!(events.len(), 3, "{:#?}", events);
+
+    assert!(matches!(events[0], TmuxEvent::KeypressSent));
+    assert!(matches!(events[1], TmuxEvent::CommandFailed(_)));
+    assert!(matches!(events[2], TmuxEvent::KeypressSent));
+}
+
+#[test]
+fn command_timeout() {
+    let mut commands = CommandTracker::default();
+    commands.push_line(vec![TmuxCommand::PaneHistorySize(0)]);
+    commands.push_line(vec![TmuxCommand::PaneHistorySize(1)]);
+    commands.lines[0][0].deadline = Instant::now();
+
+    // Overdue command is reported only once
+    let expired = commands.sweep_expired();
+    assert!(matches!(expired[..], [TmuxCommand::PaneHistorySize(0)]));
+    assert!(commands.sweep_expired().is_empty());
+
+    // Late reply is ignored, without shifting the replies which follow it
+    let transcript = "%begin 1700000000 100 0\n%end 1700000000 100 0\n\
+        %begin 1700000000 101 1\n5\n%end 1700000000 101 1\n\
+        %begin 1700000000 102 1\n6\n%end 1700000000 102 1\n";
+    let events = replay_tracked(transcript.as_bytes(), commands);
+    assert_eq!(events.len(), 1, "{:#?}", events);
+    assert!(matches!(events[0], TmuxEvent::PaneHistorySize(1, 6)));
+}
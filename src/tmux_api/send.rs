@@ -1,49 +1,479 @@
+use std::io::{self, ErrorKind, Write};
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use const_format::concatcp;
 use log::debug;
 
 use crate::{
     helpers::TmuxError,
-    keyboard::{Direction, KeyboardAction},
-    tmux_api::TmuxCommand,
+    keyboard::{Direction, KeyboardAction, LayoutPreset, TmuxKey},
+    tmux_api::{TmuxCommand, TmuxEvent},
+};
+
+use super::{
+    sessions::SESSION_FORMAT, HistoryPart, HistoryRange, PaneProperty, TmuxAPI,
+    INITIAL_HISTORY_LINES, PAUSE_AFTER_SECONDS, WINDOW_FLAGS_SUBSCRIPTION,
+    WINDOW_INDEX_SUBSCRIPTION, WINDOW_SYNC_SUBSCRIPTION,
 };
 
-use super::TmuxAPI;
+/// Mouse tracking modes of a pane, in the order of `MOUSE_MODES` (receive.rs)
+const MOUSE_MODE_FORMAT: &str = "#{mouse_standard_flag} #{mouse_button_flag} #{mouse_any_flag} #{mouse_utf8_flag} #{mouse_sgr_flag}";
+
+/// Format used for parsing Tab layouts, see `parse_tmux_layout()`
+const LAYOUT_FORMAT: &str = "#{window_id} #{window_layout} #{window_visible_layout} #{window_flags} #{window_index} #{window_name}";
+
+/// Quotes a key for `send-keys`. Single quotes stop Tmux from interpreting
+/// anything within, so they are used unless the key is a single quote itself.
+fn quote_key<T: std::fmt::Display>(key: T) -> String {
+    let key = key.to_string();
+    if key.contains('\'') {
+        format!("\"{}\"", key)
+    } else {
+        format!("'{}'", key)
+    }
+}
+
+/// Escapes text, so it can be put within double quotes. Control characters and
+/// those which mess with Tmux are replaced with octal escape sequences.
+fn escape_text(text: &str) -> String {
+    // Import write!{} trait here, otherwise it collides with
+    // use std::io::Write;
+    use std::fmt::Write;
+
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' | '\\' | '$' => write!(escaped, "\\{:03o}", c as u8).unwrap(),
+            c if c.is_ascii_control() => write!(escaped, "\\{:03o}", c as u8).unwrap(),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Groups consecutive keystrokes for the same pane into as few `send-keys`
+/// commands as possible
+fn keypress_commands(keys: Vec<(u32, TmuxKey)>) -> Vec<(TmuxCommand, String)> {
+    let mut commands = Vec::new();
+    let mut keys = keys.into_iter().peekable();
+
+    while let Some((pane_id, key)) = keys.next() {
+        let cmd = match key {
+            TmuxKey::Text(c) => {
+                let mut text = String::from(c);
+                while let Some((_, TmuxKey::Text(c))) =
+                    keys.next_if(|(id, key)| *id == pane_id && matches!(key, TmuxKey::Text(_)))
+                {
+                    text.push(c);
+                }
+
+                // Text is sent literally, so Tmux does not interpret it as key names
+                format!("send-keys -l -t %{} -- \"{}\"", pane_id, escape_text(&text))
+            }
+            TmuxKey::Named(name) => {
+                let mut names = vec![quote_key(name)];
+                while let Some((_, TmuxKey::Named(name))) =
+                    keys.next_if(|(id, key)| *id == pane_id && matches!(key, TmuxKey::Named(_)))
+                {
+                    names.push(quote_key(name));
+                }
+
+                format!("send-keys -t %{} -- {}", pane_id, names.join(" "))
+            }
+        };
+        commands.push((TmuxCommand::Keypress, cmd));
+    }
+
+    commands
+}
+
+#[inline]
+fn paste_buffer_name(pane_id: u32) -> String {
+    format!("ivyterm-paste-{}", pane_id)
+}
+
+#[inline]
+fn mouse_mode_command(pane_id: u32) -> (TmuxCommand, String) {
+    let event = TmuxCommand::PaneMouseMode(pane_id);
+    let cmd = format!(
+        "display-message -p -t %{} \"{}\"",
+        pane_id, MOUSE_MODE_FORMAT
+    );
+    (event, cmd)
+}
+
+/// How long to wait before writing again, once SSH can not take more input
+const WRITE_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long to block while writing the last command before the connection is
+/// dropped
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Tmux input stream. SSH sessions are non-blocking, so input which SSH can not
+/// take right away is kept and written later, before any newer input.
+pub struct TmuxWriter {
+    stream: Box<dyn Write>,
+    /// Input which has not been written yet, always ending with a whole line
+    pending: Vec<u8>,
+    retrying: bool,
+}
+
+impl TmuxWriter {
+    pub fn new(stream: Box<dyn Write>) -> Self {
+        Self {
+            stream,
+            pending: Vec::new(),
+            retrying: false,
+        }
+    }
+
+    /// Writes as much pending input as possible, returns true once all of it
+    /// has been written
+    fn write_pending(&mut self) -> io::Result<bool> {
+        while !self.pending.is_empty() {
+            match self.stream.write(&self.pending) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(written) => {
+                    self.pending.drain(..written);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(false),
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[inline]
+fn history_size_command(pane_id: u32) -> (TmuxCommand, String) {
+    (
+        TmuxCommand::PaneHistorySize(pane_id),
+        format!("display-message -p -t %{} \"#{{history_size}}\"", pane_id),
+    )
+}
+
+#[inline]
+fn cursor_command(event: TmuxCommand, pane_id: u32) -> (TmuxCommand, String) {
+    (
+        event,
+        format!(
+            "display-message -p -t %{} \"#{{cursor_y}} #{{cursor_x}}\"",
+            pane_id
+        ),
+    )
+}
 
 impl TmuxAPI {
     #[inline]
     fn send_event(&self, event: TmuxCommand, cmd: &str) -> Result<(), TmuxError> {
-        use std::io::Write;
+        // Keystrokes typed before this command have to reach Tmux first
+        if !self.pending_keys.borrow().is_empty() {
+            return self.send_event_list(vec![(event, cmd.to_string())]);
+        }
 
-        const NEWLINE: &[u8] = &[b'\n'];
-        // First we put the Command in Event queue
-        let command_queue = &self.command_queue;
-        command_queue
-            .send_blocking(event)
-            .map_err(|_| TmuxError::EventChannelClosed)?;
+        // First we start waiting for the Command output
+        self.commands.lock().unwrap().push_line(vec![event]);
 
         // Then we write the buffer to the Tmux input stream
+        self.write_line(cmd)
+    }
+
+    /// Sends multiple commands on a single line. Tmux executes them back to back,
+    /// without handling any pane output in between.
+    fn send_event_list(&self, events: Vec<(TmuxCommand, String)>) -> Result<(), TmuxError> {
+        // Keystrokes typed before these commands have to reach Tmux first
+        let mut events_with_keys = keypress_commands(self.pending_keys.take());
+        events_with_keys.extend(events);
+        let events = events_with_keys;
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let (events, cmds): (Vec<TmuxCommand>, Vec<String>) = events.into_iter().unzip();
+        let keys = events
+            .iter()
+            .filter(|event| matches!(event, TmuxCommand::Keypress))
+            .count();
+        self.keys_in_flight.set(self.keys_in_flight.get() + keys);
+        self.commands.lock().unwrap().push_line(events);
+
+        self.write_line(&cmds.join(" ; "))
+    }
+
+    #[inline]
+    fn write_line(&self, cmd: &str) -> Result<(), TmuxError> {
         debug!("Sending event: {}", cmd);
-        let mut stdin_stream = self.stdin_stream.borrow_mut();
-        stdin_stream
-            .write_all(cmd.as_bytes())
-            .map_err(|_| TmuxError::EventChannelClosed)?;
-        stdin_stream
-            .write_all(NEWLINE)
-            .map_err(|_| TmuxError::EventChannelClosed)?;
+        let mut writer = self.writer.borrow_mut();
+        // Whole line is queued, so Tmux never receives part of it followed by
+        // another command
+        writer.pending.extend_from_slice(cmd.as_bytes());
+        writer.pending.push(b'\n');
+        if writer.retrying {
+            return Ok(());
+        }
 
-        Ok(())
+        match writer.write_pending() {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                writer.retrying = true;
+                drop(writer);
+                self.schedule_write_retry();
+                Ok(())
+            }
+            Err(err) => {
+                debug!("Error writing to Tmux: {}", err);
+                // Failing to write to SSH means the connection dropped, which can be
+                // recovered from
+                match self.ssh_session {
+                    Some(_) => Err(TmuxError::SshClosed),
+                    None => Err(TmuxError::EventChannelClosed),
+                }
+            }
+        }
+    }
+
+    /// Writes the rest of the pending input once SSH is able to take it
+    fn schedule_write_retry(&self) {
+        let ssh = self.ssh_session.is_some();
+        let error_sender = self.write_error_sender.clone();
+        let writer = Rc::downgrade(&self.writer);
+        glib::spawn_future_local(async move {
+            loop {
+                glib::timeout_future(WRITE_RETRY_INTERVAL).await;
+                // Tmux has been closed in the meantime
+                let Some(writer) = writer.upgrade() else {
+                    return;
+                };
+
+                let result = writer.borrow_mut().write_pending();
+                match result {
+                    Ok(false) => continue,
+                    Ok(true) => {}
+                    Err(err) => {
+                        debug!("Error writing to Tmux: {}", err);
+                        let event = if ssh {
+                            TmuxEvent::Disconnected
+                        } else {
+                            TmuxEvent::Exit(None)
+                        };
+                        let _ = error_sender.send(event).await;
+                    }
+                }
+                writer.borrow_mut().retrying = false;
+                return;
+            }
+        });
+    }
+
+    /// Blocks until all pending input has been written. Needed right before the
+    /// connection is dropped, since the retry can not outlive `TmuxAPI`.
+    fn flush_input(&self) -> Result<(), TmuxError> {
+        let deadline = Instant::now() + FLUSH_TIMEOUT;
+        let mut writer = self.writer.borrow_mut();
+        loop {
+            match writer.write_pending() {
+                Ok(true) => return Ok(()),
+                Ok(false) if Instant::now() < deadline => thread::sleep(WRITE_RETRY_INTERVAL),
+                Ok(false) => {
+                    debug!("Timed out writing to Tmux");
+                    return Err(TmuxError::SshClosed);
+                }
+                Err(err) => {
+                    debug!("Error writing to Tmux: {}", err);
+                    return match self.ssh_session {
+                        Some(_) => Err(TmuxError::SshClosed),
+                        None => Err(TmuxError::EventChannelClosed),
+                    };
+                }
+            }
+        }
+    }
+
+    pub fn enable_flow_control(&self) -> Result<(), TmuxError> {
+        debug!(
+            "Enabling flow control, pause after {}s",
+            PAUSE_AFTER_SECONDS
+        );
+        let cmd = format!("refresh-client -f pause-after={}", PAUSE_AFTER_SECONDS);
+        self.send_event(TmuxCommand::FlowControl, &cmd)
+    }
+
+    /// Resumes a paused pane and redraws its screen, since Tmux drops any output
+    /// produced while the pane was paused
+    pub fn catch_up_pane(&self, pane_id: u32) -> Result<(), TmuxError> {
+        debug!("Catching up paused pane {}", pane_id);
+        let event = TmuxCommand::PaneContinue(pane_id);
+        let cmd = format!("refresh-client -A '%{}:continue'", pane_id);
+        self.send_event(event, &cmd)?;
+
+        // Cursor position has to match the captured screen exactly
+        let capture = (
+            TmuxCommand::PaneCatchUp(pane_id),
+            format!("capture-pane -p -t %{} -eC", pane_id),
+        );
+        let cursor = cursor_command(TmuxCommand::PaneCursor(pane_id), pane_id);
+        // Mouse mode might have changed while the pane was paused
+        self.send_event_list(vec![capture, cursor, mouse_mode_command(pane_id)])
+    }
+
+    /// Tmux reports the current value of each property for every pane, and then
+    /// every time it changes, using %subscription-changed
+    pub fn subscribe_pane_properties(&self) -> Result<(), TmuxError> {
+        debug!("Subscribing to pane properties");
+        let events = PaneProperty::ALL
+            .iter()
+            .map(|property| {
+                let cmd = format!(
+                    "refresh-client -B \"{}:%*:{}\"",
+                    property.subscription_name(),
+                    property.format()
+                );
+                (TmuxCommand::Subscribe, cmd)
+            })
+            .collect();
+        self.send_event_list(events)
+    }
+
+    /// Tmux reports flags and index of every window, and then every time they change
+    pub fn subscribe_windows(&self) -> Result<(), TmuxError> {
+        debug!("Subscribing to window flags, indexes and synchronized panes");
+        let flags = concatcp!(
+            "refresh-client -B \"",
+            WINDOW_FLAGS_SUBSCRIPTION,
+            ":@*:#{window_flags}\""
+        );
+        let index = concatcp!(
+            "refresh-client -B \"",
+            WINDOW_INDEX_SUBSCRIPTION,
+            ":@*:#{window_index}\""
+        );
+        let sync = concatcp!(
+            "refresh-client -B \"",
+            WINDOW_SYNC_SUBSCRIPTION,
+            ":@*:#{synchronize-panes}\""
+        );
+        self.send_event_list(vec![
+            (TmuxCommand::Subscribe, flags.to_string()),
+            (TmuxCommand::Subscribe, index.to_string()),
+            (TmuxCommand::Subscribe, sync.to_string()),
+        ])
     }
 
     pub fn get_initial_layout(&self) -> Result<(), TmuxError> {
         debug!("Getting initial layout");
-        let cmd = "list-windows -F \"#{window_id} #{window_layout} #{window_visible_layout} #{window_flags} #{window_name}\"";
+        let cmd = concatcp!("list-windows -F \"", LAYOUT_FORMAT, "\"");
         self.send_event(TmuxCommand::InitialLayout, cmd)
     }
 
+    pub fn get_tab_layout(&self, tab_id: u32) -> Result<(), TmuxError> {
+        debug!("Getting layout of tab {}", tab_id);
+        // Filter is needed, since the window might not belong to our session
+        let event = TmuxCommand::TabLayout(tab_id);
+        let cmd = format!(
+            "list-windows -f \"#{{==:#{{window_id}},@{}}}\" -F \"{}\"",
+            tab_id, LAYOUT_FORMAT
+        );
+        self.send_event(event, &cmd)
+    }
+
+    pub fn get_sessions(&self) -> Result<(), TmuxError> {
+        debug!("Listing sessions");
+        let cmd = concatcp!("list-sessions -F \"", SESSION_FORMAT, "\"");
+        self.send_event(TmuxCommand::SessionList, cmd)
+    }
+
+    /// Tmux notifies us with %session-changed once the client has switched
+    pub fn switch_session(&self, session_id: u32) -> Result<(), TmuxError> {
+        debug!("Switching to session {}", session_id);
+        let event = TmuxCommand::SessionSwitch(session_id);
+        let cmd = format!("switch-client -t '${}'", session_id);
+        self.send_event(event, &cmd)
+    }
+
     pub fn get_initial_output(&self, pane_id: u32) -> Result<(), TmuxError> {
         debug!("Getting initial output of pane {}", pane_id);
-        let event = TmuxCommand::InitialOutput(pane_id);
-        let cmd = format!("capture-pane -J -p -t %{} -eC -S - -E -", pane_id);
-        self.send_event(event, &cmd)
+        // Only the tail of the history is captured, so attaching to panes with a
+        // huge history is fast. Older lines are loaded using `get_pane_history()`.
+        let capture = (
+            TmuxCommand::InitialOutput(pane_id),
+            format!(
+                "capture-pane -J -p -t %{} -eC -S -{} -E -",
+                pane_id, INITIAL_HISTORY_LINES
+            ),
+        );
+        // Captured output does not include the escape sequences which enabled mouse
+        // tracking, so we query the mode separately
+        self.send_event_list(vec![
+            history_size_command(pane_id),
+            capture,
+            mouse_mode_command(pane_id),
+        ])
+    }
+
+    /// Without a range, only asks for the history size, which the ranges are
+    /// computed from. Otherwise captures the given ranges of history, along with
+    /// the screen and cursor position, since VTE can not prepend lines to its
+    /// scrollback and the whole Terminal content has to be fed again.
+    ///
+    /// History size is asked for again on the same line, so the Terminal can tell
+    /// whether new lines moved the ranges before they were captured.
+    pub fn get_pane_history(
+        &self,
+        pane_id: u32,
+        range: Option<HistoryRange>,
+    ) -> Result<(), TmuxError> {
+        let size = (
+            TmuxCommand::PaneHistory(pane_id, HistoryPart::Size),
+            format!(
+                "display-message -p -t %{} \"#{{history_size}} #{{history_limit}}\"",
+                pane_id
+            ),
+        );
+        let Some(range) = range else {
+            debug!("Getting history size of pane {}", pane_id);
+            return self.send_event_list(vec![size]);
+        };
+        debug!("Loading history of pane {}: {:?}", pane_id, range);
+
+        let mut events = vec![size];
+        if let Some((start, end)) = range.older {
+            events.push((
+                TmuxCommand::PaneHistory(pane_id, HistoryPart::Older),
+                format!(
+                    "capture-pane -J -p -t %{} -eC -S -{} -E -{}",
+                    pane_id, start, end
+                ),
+            ));
+        }
+        if range.recent > 0 {
+            events.push((
+                TmuxCommand::PaneHistory(pane_id, HistoryPart::Recent),
+                format!(
+                    "capture-pane -J -p -t %{} -eC -S -{} -E -1",
+                    pane_id, range.recent
+                ),
+            ));
+        }
+        // Screen is not joined, so it has exactly as many lines as the pane
+        events.push((
+            TmuxCommand::PaneHistory(pane_id, HistoryPart::Screen),
+            format!("capture-pane -p -t %{} -eC", pane_id),
+        ));
+        events.push(cursor_command(
+            TmuxCommand::PaneHistory(pane_id, HistoryPart::Cursor),
+            pane_id,
+        ));
+        self.send_event_list(events)
+    }
+
+    /// Sends a mouse report generated by VTE as raw bytes
+    pub fn send_mouse_report(&self, pane_id: u32, report: &[u8]) -> Result<(), TmuxError> {
+        let hex: Vec<String> = report.iter().map(|b| format!("{:02x}", b)).collect();
+        let cmd = format!("send-keys -H -t %{} {}", pane_id, hex.join(" "));
+        self.send_event(TmuxCommand::MouseReport, &cmd)
     }
 
     pub fn change_size(&self, cols: i32, rows: i32) -> Result<(), TmuxError> {
@@ -63,69 +493,56 @@ impl TmuxAPI {
         self.send_event(event, &cmd)
     }
 
-    pub fn send_keypress(
-        &self,
-        pane_id: u32,
-        c: char,
-        prefix: String,
-        movement: Option<&str>,
-    ) -> Result<(), TmuxError> {
-        let cmd = if let Some(control) = movement {
-            // Navigation keys (left, right, page up, ...)
-            format!("send-keys -t %{} {}{}", pane_id, prefix, control)
-        } else if c.is_ascii_control() {
-            // A control character was just pressed
-            let ascii = c as u8;
-            // Ignore Ctrl and Shift for Enter
-            let prefix = if ascii == 13 { "" } else { prefix.as_str() };
-            format!("send-keys -t %{} -- {}\\{:03o}", pane_id, prefix, ascii)
-        } else {
-            // We send single-quoted keys, but what if we want to send a single quote?
-            let quote = if c == '\'' { '"' } else { '\'' };
-
-            // If Ctrl/Shift/Alt was pressed, prefix will not be empty and we need to
-            // remove Tmux's -l flag
-            let flags = if prefix.is_empty() { "-l" } else { "" };
-
-            format!(
-                "send-keys -t %{} {} -- {}{}{}{}",
-                pane_id, flags, quote, prefix, c, quote
-            )
-        };
-
-        debug!("send_keypress: {}", &cmd[..cmd.len() - 1]);
-        self.send_event(TmuxCommand::Keypress, &cmd)
+    /// Keystrokes are batched until `flush_keypresses()` or until any other
+    /// command is sent
+    pub fn send_keypress(&self, pane_id: u32, key: TmuxKey) {
+        self.pending_keys.borrow_mut().push((pane_id, key));
     }
 
-    pub fn send_quoted_text(&self, pane_id: u32, text: &str) -> Result<(), TmuxError> {
-        // Escape content
-        let mut escaped = String::with_capacity(text.len());
+    /// Text is batched together with keystrokes, see `send_keypress()`
+    pub fn send_quoted_text(&self, pane_id: u32, text: &str) {
+        let mut pending_keys = self.pending_keys.borrow_mut();
         for c in text.chars() {
-            // Import write!{} trait here, otherwise it collides with
-            // use std::io::Write;
-            use std::fmt::Write;
-
-            match c {
-                // These characters mess with Tmux
-                '\n' | '"' | '\\' | '$' => {
-                    let ascii = c as u8;
-                    write!(escaped, "\\{:03o}", ascii).unwrap();
-                }
-                _ => escaped.push(c),
-            }
+            pending_keys.push((pane_id, TmuxKey::Text(c)));
         }
+    }
 
-        let cmd = format!("send-keys -l -t %{} -- \"{}\"", pane_id, escaped);
-        debug!("send_clipboard: {}", &cmd[..cmd.len() - 1]);
-        self.send_event(TmuxCommand::ClipboardPaste, &cmd)
+    pub fn flush_keypresses(&self) -> Result<(), TmuxError> {
+        self.send_event_list(Vec::new())
     }
 
-    // TODO: Too many functions for sending text
-    pub fn send_function_key(&self, pane_id: u32, text: &str) -> Result<(), TmuxError> {
-        let cmd = format!("send-keys -t %{} -- \"{}\"", pane_id, text);
+    /// Keystrokes sent before have not been answered by Tmux yet
+    pub fn keypresses_in_flight(&self) -> bool {
+        self.keys_in_flight.get() > 0
+    }
 
-        debug!("send_function_key: {}", &cmd[..cmd.len() - 1]);
-        self.send_event(TmuxCommand::Keypress, &cmd)
+    pub fn keypress_answered(&self) {
+        let keys_in_flight = self.keys_in_flight.get();
+        self.keys_in_flight.set(keys_in_flight.saturating_sub(1));
+    }
+
+    /// Appends a chunk of text to the paste buffer of the given pane
+    pub fn paste_chunk(&self, pane_id: u32, chunk: &str, append: bool) -> Result<(), TmuxError> {
+        let event = TmuxCommand::PasteChunk(pane_id);
+        let cmd = format!(
+            "set-buffer -b {}{} -- \"{}\"",
+            paste_buffer_name(pane_id),
+            if append { " -a" } else { "" },
+            escape_text(chunk)
+        );
+        self.send_event(event, &cmd)
+    }
+
+    /// Pastes (and deletes) the paste buffer filled by `paste_chunk()`. Tmux uses
+    /// bracketed paste if the application running in the pane requested it.
+    pub fn paste_buffer(&self, pane_id: u32) -> Result<(), TmuxError> {
+        let event = TmuxCommand::ClipboardPaste(pane_id);
+        let cmd = format!(
+            "paste-buffer -p -d -b {} -t %{}",
+            paste_buffer_name(pane_id),
+            pane_id
+        );
+        self.send_event(event, &cmd)
     }
 
     pub fn send_keybinding(&self, action: KeyboardAction, pane_id: u32) -> Result<(), TmuxError> {
@@ -145,11 +562,8 @@ impl TmuxAPI {
                 (event, cmd)
             }
             KeyboardAction::TabNew => {
-                // TODO: We should get all required layout info without having to ask directly,
-                // since it would allow us to react to external commands
-                let cmd = String::from(
-                    "new-window -P -F \"#{window_id} #{window_layout} #{window_visible_layout} ${window_flags} #{window_name}\"",
-                );
+                // Tab itself is created once Tmux notifies us with %window-add
+                let cmd = String::from("new-window");
                 (TmuxCommand::TabNew, cmd)
             }
             KeyboardAction::TabClose => {
@@ -173,6 +587,52 @@ impl TmuxAPI {
                 let event = TmuxCommand::PaneMoveFocus(direction);
                 (event, cmd)
             }
+            KeyboardAction::PaneSwap(direction) => {
+                return self.swap_pane(pane_id, direction);
+            }
+            KeyboardAction::PaneBreak => {
+                return self.break_pane(pane_id);
+            }
+            KeyboardAction::PaneJoin => {
+                debug!("PaneJoin keyboard event needs to be handled by Window widget");
+                return Ok(());
+            }
+            KeyboardAction::TabRotate => {
+                return self.rotate_tab(pane_id);
+            }
+            KeyboardAction::TabLayout(preset) => {
+                let cmd = format!(
+                    "select-layout -t %{} {}",
+                    pane_id,
+                    match preset {
+                        LayoutPreset::EvenHorizontal => "even-horizontal",
+                        LayoutPreset::EvenVertical => "even-vertical",
+                        LayoutPreset::MainHorizontal => "main-horizontal",
+                        LayoutPreset::MainVertical => "main-vertical",
+                        LayoutPreset::Tiled => "tiled",
+                    }
+                );
+                (TmuxCommand::TabSelectLayout(pane_id), cmd)
+            }
+            KeyboardAction::TabNextLayout => {
+                let cmd = format!("next-layout -t %{}", pane_id);
+                (TmuxCommand::TabSelectLayout(pane_id), cmd)
+            }
+            KeyboardAction::TabSynchronize => {
+                // Flag options are toggled when no value is given
+                let cmd = format!("set-option -w -t %{} synchronize-panes", pane_id);
+                (TmuxCommand::TabSynchronize(pane_id), cmd)
+            }
+            KeyboardAction::SessionDetach => {
+                return self.detach();
+            }
+            KeyboardAction::SessionKill => {
+                return self.kill_session();
+            }
+            KeyboardAction::TabLayoutSave | KeyboardAction::TabLayoutRestore => {
+                debug!("Saving and restoring layouts needs to be handled by Window widget");
+                return Ok(());
+            }
             KeyboardAction::ToggleZoom => {
                 let cmd = format!("resize-pane -Z -t %{}", pane_id);
                 let event = TmuxCommand::PaneZoom(pane_id);
@@ -185,13 +645,7 @@ impl TmuxAPI {
                 panic!("PasteClipboard keyboard event needs to be handled by Terminal widget");
             }
             KeyboardAction::OpenEditorCwd => {
-                // TODO: This prints ALL panes in a Tab, not needed
-                let event = TmuxCommand::PaneCurrentPath(pane_id);
-                let cmd = format!(
-                    "list-panes -t %{} -F \"path: #{{pane_id}} #{{pane_current_path}}\"",
-                    pane_id
-                );
-                (event, cmd)
+                panic!("OpenEditorCwd keyboard event needs to be handled by Terminal widget");
             }
             KeyboardAction::ClearScrollback => {
                 let event = TmuxCommand::ClearScrollback(pane_id);
@@ -203,6 +657,87 @@ impl TmuxAPI {
         self.send_event(event, &cmd)
     }
 
+    /// Swaps positions of two Tabs, without changing the selected one
+    pub fn swap_tabs(&self, tab_id: u32, other_id: u32) -> Result<(), TmuxError> {
+        let event = TmuxCommand::TabMove(tab_id);
+        let cmd = format!("swap-window -d -s @{} -t @{}", tab_id, other_id);
+        self.send_event(event, &cmd)
+    }
+
+    /// Moves the Tab right after (or before) the target Tab. Tmux shifts the
+    /// indexes of other Tabs to make room.
+    pub fn move_tab(&self, tab_id: u32, target_id: u32, after: bool) -> Result<(), TmuxError> {
+        let event = TmuxCommand::TabMove(tab_id);
+        let cmd = format!(
+            "move-window -d {} -s @{} -t @{}",
+            if after { "-a" } else { "-b" },
+            tab_id,
+            target_id
+        );
+        self.send_event(event, &cmd)
+    }
+
+    /// Swaps the pane with its neighbour in the given direction. Layout changes
+    /// are reported with %layout-change.
+    pub fn swap_pane(&self, pane_id: u32, direction: Direction) -> Result<(), TmuxError> {
+        // Target is relative to the active pane, which is the focused one
+        let event = TmuxCommand::PaneSwap(pane_id);
+        let cmd = format!(
+            "swap-pane -s %{} -t '{}'",
+            pane_id,
+            match direction {
+                Direction::Down => "{down-of}",
+                Direction::Left => "{left-of}",
+                Direction::Right => "{right-of}",
+                Direction::Up => "{up-of}",
+            }
+        );
+        self.send_event(event, &cmd)
+    }
+
+    /// Moves the pane into a new Tab, which Tmux reports with %window-add
+    pub fn break_pane(&self, pane_id: u32) -> Result<(), TmuxError> {
+        let event = TmuxCommand::PaneBreak(pane_id);
+        let cmd = format!("break-pane -s %{}", pane_id);
+        self.send_event(event, &cmd)
+    }
+
+    /// Moves the pane into the given Tab, next to its active pane
+    pub fn join_pane(&self, pane_id: u32, tab_id: u32) -> Result<(), TmuxError> {
+        let event = TmuxCommand::PaneJoin(pane_id);
+        let cmd = format!("join-pane -h -s %{} -t @{}", pane_id, tab_id);
+        self.send_event(event, &cmd)
+    }
+
+    /// Rotates panes of the Tab containing the given pane
+    pub fn rotate_tab(&self, pane_id: u32) -> Result<(), TmuxError> {
+        let event = TmuxCommand::TabRotate(pane_id);
+        let cmd = format!("rotate-window -t %{}", pane_id);
+        self.send_event(event, &cmd)
+    }
+
+    /// Applies a layout previously read from `window_layout`. Tmux refuses it if
+    /// the number of panes does not match.
+    pub fn apply_layout(&self, tab_id: u32, layout: &str) -> Result<(), TmuxError> {
+        let event = TmuxCommand::TabApplyLayout(tab_id);
+        let cmd = format!("select-layout -t @{} '{}'", tab_id, layout);
+        self.send_event(event, &cmd)
+    }
+
+    /// Detaches from the session, which keeps running. Tmux then exits with %exit.
+    /// Input is written before returning, so the connection can be dropped.
+    pub fn detach(&self) -> Result<(), TmuxError> {
+        self.send_event(TmuxCommand::Detach, "detach-client")?;
+        self.flush_input()
+    }
+
+    /// Kills the attached session, including all programs running within it.
+    /// Input is written before returning, so the connection can be dropped.
+    pub fn kill_session(&self) -> Result<(), TmuxError> {
+        self.send_event(TmuxCommand::SessionKill, "kill-session")?;
+        self.flush_input()
+    }
+
     pub fn select_tab(&self, tab_id: u32) -> Result<(), TmuxError> {
         let event = TmuxCommand::TabSelect(tab_id);
         let cmd = format!("select-window -t @{}", tab_id);
@@ -215,6 +750,11 @@ impl TmuxAPI {
         self.send_event(event, &cmd)
     }
 
+    /// Forgets the last Tmux client size, so the next `change_size()` is always sent
+    pub fn reset_size(&self) {
+        self.window_size.replace((0, 0));
+    }
+
     /// Updates resize_future to `new` value, while returning the old value
     pub fn update_resize_future(&self, new: bool) -> bool {
         self.resize_future.replace(new)
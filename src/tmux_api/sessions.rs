@@ -0,0 +1,97 @@
+use std::io::Read;
+use std::process::Command;
+
+use log::debug;
+
+use crate::helpers::IvyError;
+use crate::ssh::{new_session, SshPrompter};
+
+/// Format of a single `list-sessions` line, parsed by `parse_session()`
+pub const SESSION_FORMAT: &str =
+    "#{session_id} #{session_windows} #{session_attached} #{session_activity} #{session_name}";
+
+#[derive(Debug, Clone)]
+pub struct TmuxSession {
+    pub id: u32,
+    pub name: String,
+    pub windows: u32,
+    /// Number of clients attached to the session
+    pub attached: u32,
+    /// Unix timestamp of the last activity
+    pub activity: i64,
+}
+
+/// Parses a line formatted with `SESSION_FORMAT`, e.g. "$1 3 0 1700000000 name"
+pub fn parse_session(line: &str) -> Option<TmuxSession> {
+    // Session name is last, since it may contain spaces
+    let mut fields = line.trim_end().splitn(5, ' ');
+    let id = fields.next()?.strip_prefix('$')?.parse().ok()?;
+    let windows = fields.next()?.parse().ok()?;
+    let attached = fields.next()?.parse().ok()?;
+    let activity = fields.next()?.parse().ok()?;
+    let name = fields.next()?.to_string();
+
+    Some(TmuxSession {
+        id,
+        name,
+        windows,
+        attached,
+        activity,
+    })
+}
+
+fn parse_session_list(output: &str) -> Vec<TmuxSession> {
+    output.lines().filter_map(parse_session).collect()
+}
+
+/// Lists sessions of the local Tmux server, or the one on the SSH host. This
+/// function blocks, so it should be called using `gio::spawn_blocking()`.
+pub fn list_sessions(ssh_host: Option<(&str, &SshPrompter)>) -> Result<Vec<TmuxSession>, IvyError> {
+    let output = if let Some((ssh_target, prompter)) = ssh_host {
+        list_sessions_ssh(ssh_target, prompter)?
+    } else {
+        let output = Command::new("tmux")
+            .arg("list-sessions")
+            .arg("-F")
+            .arg(SESSION_FORMAT)
+            .output()
+            .map_err(|err| {
+                eprintln!("Could not run tmux list-sessions: {}", err);
+                IvyError::TmuxSpawnFailed
+            })?;
+
+        // Tmux fails when no server is running, meaning there are no sessions
+        if !output.status.success() {
+            debug!(
+                "tmux list-sessions failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            return Ok(vec![]);
+        }
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    Ok(parse_session_list(&output))
+}
+
+fn list_sessions_ssh(ssh_target: &str, prompter: &SshPrompter) -> Result<String, IvyError> {
+    let ssh_data = new_session(ssh_target, prompter).map_err(|_| IvyError::SshFailed)?;
+    let session = ssh_data.1;
+
+    let command = format!("tmux list-sessions -F '{}'", SESSION_FORMAT);
+    let mut channel = session.channel_session().map_err(|_| IvyError::SshFailed)?;
+    channel.exec(&command).map_err(|err| {
+        eprintln!("channel.exec() failed with: {}", err);
+        IvyError::TmuxSpawnFailed
+    })?;
+
+    // Tmux prints nothing to stdout when no server is running
+    let mut output = String::new();
+    if let Err(err) = channel.read_to_string(&mut output) {
+        debug!("Error reading tmux list-sessions output: {}", err);
+    }
+    let _ = channel.wait_close();
+    let _ = session.disconnect(None, "Listed sessions", None);
+
+    Ok(output)
+}
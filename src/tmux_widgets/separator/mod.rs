@@ -51,7 +51,7 @@ impl TmuxSeparator {
         // Change the cursor when hovering separator and container
         let cursor = Cursor::from_name(cursor, None);
         if let Some(cursor) = cursor.as_ref() {
-            bin.set_cursor(Some(&cursor));
+            bin.set_cursor(Some(cursor));
         }
 
         // Add ability to drag
@@ -0,0 +1,301 @@
+use std::mem::take;
+
+use crate::tmux_api::{
+    HistoryCapture, HistoryPart, HistoryRange, HISTORY_PAGE_LINES, INITIAL_HISTORY_LINES,
+};
+
+/// Times a page is planned from a new history size, because output moved the
+/// lines before they were captured. Last attempt captures the whole history.
+const HISTORY_ATTEMPTS: u32 = 3;
+
+/// History of a pane which has been captured so far. VTE can not prepend lines
+/// to its scrollback, so each page is prepended here and the Terminal is filled
+/// again with all of it.
+///
+/// Lines are identified by their age (0 being the oldest line Tmux keeps), since
+/// their position relative to the screen changes with every line of output.
+#[derive(Default)]
+pub struct PaneHistory {
+    /// Captured lines, oldest first
+    lines: Vec<Vec<u8>>,
+    /// Age of the oldest captured line
+    start: u32,
+    /// Age following the newest captured line. Newer lines are shown by the
+    /// Terminal, but have to be captured before it is filled again.
+    end: u32,
+    reload: Option<HistoryReload>,
+}
+
+/// Page of history which is being loaded
+#[derive(Default)]
+struct HistoryReload {
+    /// History size and limit the range was planned for
+    planned: Option<(u32, u32)>,
+    range: Option<HistoryRange>,
+    /// Lines of the older page
+    page: u32,
+    /// Whole history is captured again, instead of the missing lines only
+    full: bool,
+    attempt: u32,
+    /// History size and limit at the time the range was captured
+    reported: Option<(u32, u32)>,
+    older: Option<Vec<Vec<u8>>>,
+    recent: Option<Vec<Vec<u8>>>,
+    screen: Option<Vec<Vec<u8>>>,
+    cursor: Option<Vec<u8>>,
+}
+
+pub enum HistoryLoaded {
+    /// Output replacing the whole Terminal content
+    Output(Vec<u8>),
+    /// Captured lines are outdated, this range has to be captured instead
+    Retry(HistoryRange),
+    Failed,
+}
+
+impl PaneHistory {
+    /// Initial capture includes the newest lines of a history of `size` lines
+    pub fn reset(&mut self, size: u32) {
+        let start = size.saturating_sub(INITIAL_HISTORY_LINES);
+        *self = Self {
+            start,
+            end: start,
+            ..Default::default()
+        };
+    }
+
+    /// Starts loading the next page, returns false if there is nothing to load
+    /// or VTE is not able to keep more lines
+    pub fn start_reload(&mut self, max_lines: u32) -> bool {
+        if self.reload.is_some() || self.start == 0 || self.end - self.start >= max_lines {
+            return false;
+        }
+        self.reload = Some(HistoryReload::default());
+        true
+    }
+
+    /// Stores a captured part, returns the range to capture once the history size
+    /// the range depends on is known
+    pub fn captured(&mut self, capture: HistoryCapture) -> Option<HistoryRange> {
+        let reload = self.reload.as_mut()?;
+        match capture {
+            HistoryCapture::Size(size, limit) => {
+                if reload.planned.is_none() {
+                    return Some(self.plan(size, limit, 1));
+                }
+                reload.reported = Some((size, limit));
+            }
+            HistoryCapture::Lines(HistoryPart::Older, lines) => reload.older = Some(lines),
+            HistoryCapture::Lines(HistoryPart::Recent, lines) => reload.recent = Some(lines),
+            HistoryCapture::Lines(_, lines) => reload.screen = Some(lines),
+            HistoryCapture::Cursor(cursor) => reload.cursor = Some(cursor),
+        }
+        None
+    }
+
+    fn plan(&mut self, size: u32, limit: u32, attempt: u32) -> HistoryRange {
+        // Once the history is full, Tmux drops its oldest lines and the age of all
+        // other lines changes. Same happens when the history is cleared.
+        let trimmed = size >= limit - limit / 10 || size < self.end;
+        let full = trimmed || attempt >= HISTORY_ATTEMPTS;
+        let page = HISTORY_PAGE_LINES.min(self.start);
+
+        let range = if full {
+            let lines = (size.max(self.end) - self.start + page).min(size);
+            HistoryRange {
+                older: None,
+                recent: lines,
+            }
+        } else {
+            let older = (page > 0).then(|| (size - self.start + page, size - self.start + 1));
+            HistoryRange {
+                older,
+                recent: size - self.end,
+            }
+        };
+
+        self.reload = Some(HistoryReload {
+            planned: Some((size, limit)),
+            range: Some(range),
+            page,
+            full,
+            attempt,
+            ..Default::default()
+        });
+        range
+    }
+
+    /// Puts the captured parts together once the last one has been received
+    pub fn loaded(&mut self) -> HistoryLoaded {
+        let Some(mut reload) = self.reload.take() else {
+            return HistoryLoaded::Failed;
+        };
+        let (Some(range), Some((size, limit)), Some(screen), Some(cursor)) = (
+            reload.range,
+            reload.reported,
+            reload.screen.take(),
+            reload.cursor.take(),
+        ) else {
+            return HistoryLoaded::Failed;
+        };
+
+        let recent = match reload.recent.take() {
+            Some(recent) => recent,
+            None if range.recent == 0 => Vec::new(),
+            None => return HistoryLoaded::Failed,
+        };
+        if reload.full {
+            self.lines = recent;
+            self.start = size - range.recent.min(size);
+        } else {
+            // Output moved the lines before they were captured
+            if reload.planned != Some((size, limit)) {
+                return HistoryLoaded::Retry(self.plan(size, limit, reload.attempt + 1));
+            }
+
+            let older = match reload.older.take() {
+                Some(older) => older,
+                None if range.older.is_none() => Vec::new(),
+                None => return HistoryLoaded::Failed,
+            };
+            let lines = take(&mut self.lines);
+            self.lines = older.into_iter().chain(lines).chain(recent).collect();
+            self.start -= reload.page;
+        }
+        self.end = size;
+
+        // Terminal is cleared, including the scrollback, and filled again
+        let mut output = b"\x1b[H\x1b[2J\x1b[3J".to_vec();
+        let mut lines = self.lines.iter().chain(screen.iter());
+        if let Some(line) = lines.next() {
+            output.extend_from_slice(line);
+        }
+        for line in lines {
+            output.extend_from_slice(b"\r\n");
+            output.extend_from_slice(line);
+        }
+        output.extend_from_slice(&cursor);
+        HistoryLoaded::Output(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> Vec<Vec<u8>> {
+        lines.iter().map(|line| line.as_bytes().to_vec()).collect()
+    }
+
+    /// Feeds the parts Tmux would send for `range`
+    fn capture(
+        history: &mut PaneHistory,
+        size: u32,
+        older: &[&str],
+        recent: &[&str],
+    ) -> HistoryLoaded {
+        assert!(history
+            .captured(HistoryCapture::Size(size, 50000))
+            .is_none());
+        if !older.is_empty() {
+            history.captured(HistoryCapture::Lines(HistoryPart::Older, lines(older)));
+        }
+        if !recent.is_empty() {
+            history.captured(HistoryCapture::Lines(HistoryPart::Recent, lines(recent)));
+        }
+        history.captured(HistoryCapture::Lines(HistoryPart::Screen, lines(&["$ "])));
+        history.captured(HistoryCapture::Cursor(b"\x1b[1;3H".to_vec()));
+        history.loaded()
+    }
+
+    #[test]
+    fn pages() {
+        let mut history = PaneHistory::default();
+        history.reset(INITIAL_HISTORY_LINES + 2500);
+
+        // Newest lines were captured initially, they are captured once more
+        assert!(history.start_reload(u32::MAX));
+        let range = history.captured(HistoryCapture::Size(3600, 50000));
+        let expected = HistoryRange {
+            older: Some((3100, 1101)),
+            recent: 1100,
+        };
+        assert_eq!(range, Some(expected));
+        match capture(&mut history, 3600, &["a", "b"], &["c"]) {
+            HistoryLoaded::Output(output) => {
+                assert_eq!(output, b"\x1b[H\x1b[2J\x1b[3Ja\r\nb\r\nc\r\n$ \x1b[1;3H")
+            }
+            _ => panic!("History was not loaded"),
+        }
+
+        // Last page is shorter, and only lines added since then are captured
+        assert!(history.start_reload(u32::MAX));
+        let range = history.captured(HistoryCapture::Size(3610, 50000));
+        let expected = HistoryRange {
+            older: Some((3610, 3111)),
+            recent: 10,
+        };
+        assert_eq!(range, Some(expected));
+        match capture(&mut history, 3610, &["z"], &["d"]) {
+            HistoryLoaded::Output(output) => {
+                assert_eq!(
+                    output,
+                    b"\x1b[H\x1b[2J\x1b[3Jz\r\na\r\nb\r\nc\r\nd\r\n$ \x1b[1;3H"
+                )
+            }
+            _ => panic!("History was not loaded"),
+        }
+        assert!(!history.start_reload(u32::MAX));
+    }
+
+    #[test]
+    fn moved_lines() {
+        let mut history = PaneHistory::default();
+        history.reset(5000);
+        assert!(history.start_reload(u32::MAX));
+        history.captured(HistoryCapture::Size(5000, 50000));
+
+        // Lines moved before the capture, so it is planned again
+        let expected = HistoryRange {
+            older: Some((3003, 1004)),
+            recent: 1003,
+        };
+        match capture(&mut history, 5003, &["old"], &["new"]) {
+            HistoryLoaded::Retry(range) => assert_eq!(range, expected),
+            _ => panic!("Moved lines were not captured again"),
+        }
+
+        // Last attempt captures everything, which works regardless of moved lines
+        let expected = HistoryRange {
+            older: None,
+            recent: 3006,
+        };
+        match capture(&mut history, 5006, &["old"], &["new"]) {
+            HistoryLoaded::Retry(range) => assert_eq!(range, expected),
+            _ => panic!("Moved lines were not captured again"),
+        }
+        match capture(&mut history, 5020, &[], &["all"]) {
+            HistoryLoaded::Output(output) => {
+                assert_eq!(output, b"\x1b[H\x1b[2J\x1b[3Jall\r\n$ \x1b[1;3H")
+            }
+            _ => panic!("History was not loaded"),
+        }
+        assert_eq!((history.start, history.end), (5020 - 3006, 5020));
+    }
+
+    #[test]
+    fn missing_parts() {
+        let mut history = PaneHistory::default();
+        history.reset(5000);
+        assert!(history.start_reload(u32::MAX));
+        history.captured(HistoryCapture::Size(5000, 50000));
+
+        // Older page was never captured
+        assert!(matches!(
+            capture(&mut history, 5000, &[], &["new"]),
+            HistoryLoaded::Failed
+        ));
+        assert_eq!((history.start, history.end), (4000, 4000));
+        assert!(history.start_reload(u32::MAX));
+    }
+}
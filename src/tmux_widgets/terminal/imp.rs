@@ -1,13 +1,27 @@
 use std::cell::{Cell, RefCell};
 
+use gtk4::Label;
 use libadwaita::{glib, prelude::*, subclass::prelude::*};
 use vte4::Terminal as Vte;
 
+use super::history::PaneHistory;
+
 // Object holding the state
 #[derive(Default)]
 pub struct TerminalPriv {
     pub vte: RefCell<Option<Vte>>,
     pub id: Cell<u32>,
+    pub status: RefCell<Option<Label>>,
+    pub paused: Cell<bool>,
+    pub history: RefCell<PaneHistory>,
+    /// While history is loading, holds the distance of the view from the bottom
+    pub history_reload: Cell<Option<f64>>,
+    /// Output received before the initial capture has been fed
+    pub pending_output: RefCell<Vec<u8>>,
+    /// Pane properties reported by Tmux subscriptions
+    pub current_path: RefCell<String>,
+    pub current_command: RefCell<String>,
+    pub title: RefCell<String>,
     initial_output: Cell<bool>,
 }
 
@@ -23,6 +37,7 @@ impl ObjectSubclass for TerminalPriv {
 impl ObjectImpl for TerminalPriv {
     fn dispose(&self) {
         self.vte.take();
+        self.status.take();
     }
 }
 
@@ -39,9 +54,10 @@ impl WidgetImpl for TerminalPriv {
 impl BinImpl for TerminalPriv {}
 
 impl TerminalPriv {
-    pub fn init_values(&self, id: u32, terminal: &Vte) {
+    pub fn init_values(&self, id: u32, terminal: &Vte, status: &Label) {
         self.id.replace(id);
         self.vte.borrow_mut().replace(terminal.clone());
+        self.status.borrow_mut().replace(status.clone());
     }
 
     pub fn is_synced(&self) -> bool {
@@ -51,4 +67,8 @@ impl TerminalPriv {
     pub fn set_synced(&self) {
         self.initial_output.replace(true);
     }
+
+    pub fn set_unsynced(&self) {
+        self.initial_output.replace(false);
+    }
 }
@@ -1,11 +1,13 @@
+mod history;
 mod imp;
 
 use glib::{subclass::types::ObjectSubclassIsExt, Object, Propagation};
 use gtk4::{
-    gdk::{ModifierType, BUTTON_PRIMARY},
-    gio, EventControllerKey, GestureClick, ScrolledWindow,
+    gdk::{ModifierType, Rectangle, BUTTON_PRIMARY},
+    gio, Align, EventControllerKey, GestureClick, IMMulticontext, Label, Overlay, ScrolledWindow,
 };
 use libadwaita::{glib, prelude::*};
+use log::debug;
 use vte4::{Regex, Terminal as Vte, TerminalExt, TerminalExtManual};
 
 use crate::{
@@ -13,11 +15,18 @@ use crate::{
     config::{ColorScheme, TerminalConfig},
     helpers::{borrow_clone, PCRE2_MULTILINE, URL_REGEX_STRINGS},
     keyboard::KeyboardAction,
+    tmux_api::{HistoryCapture, HistoryRange, PaneProperty},
     unwrap_or_return,
 };
 
+use history::{HistoryLoaded, PaneHistory};
+
 use super::{toplevel::TmuxTopLevel, IvyTmuxWindow};
 
+/// Output kept for a pane which can not show it yet. Beyond this, the pane is
+/// captured instead.
+pub const PENDING_OUTPUT_LIMIT: usize = 1024 * 1024;
+
 glib::wrapper! {
     pub struct TmuxTerminal(ObjectSubclass<imp::TerminalPriv>)
         @extends libadwaita::Bin, gtk4::Widget,
@@ -48,10 +57,20 @@ impl TmuxTerminal {
             .vscrollbar_policy(gtk4::PolicyType::Always)
             .build();
 
+        // Status label is shown on top of the Terminal, for example while catching up
+        let status = Label::builder()
+            .halign(Align::End)
+            .valign(Align::Start)
+            .css_classes(["terminal_status"])
+            .visible(false)
+            .build();
+        let overlay = Overlay::builder().child(&scrolled).build();
+        overlay.add_overlay(&status);
+
         // Create self
         let terminal: Self = Object::builder().build();
-        terminal.set_child(Some(&scrolled));
-        terminal.imp().init_values(pane_id, &vte);
+        terminal.set_child(Some(&overlay));
+        terminal.imp().init_values(pane_id, &vte, &status);
 
         if window.initial_layout_finished() {
             terminal.imp().set_synced();
@@ -79,7 +98,74 @@ impl TmuxTerminal {
             }
         ));
 
+        // Once the remote application enables mouse tracking (which we mirror in VTE),
+        // VTE generates mouse reports for clicks, drags and wheel. Holding Shift
+        // bypasses mouse tracking, so local selection keeps working.
+        vte.connect_commit(glib::clone!(
+            #[weak]
+            window,
+            move |_, text, _| {
+                if is_mouse_report(text) {
+                    window.send_mouse_report(pane_id, text.as_bytes());
+                }
+            }
+        ));
+
+        // Text input goes through an input method, which handles dead keys, compose
+        // sequences and CJK input. Only keys which do not produce text (and those
+        // with Ctrl/Alt) reach key-pressed.
+        let im_context = IMMulticontext::new();
+        im_context.set_client_widget(Some(&vte));
+        im_context.connect_commit(glib::clone!(
+            #[weak]
+            window,
+            move |_, text| {
+                window.send_text(pane_id, text);
+            }
+        ));
+        im_context.connect_preedit_start(glib::clone!(
+            #[weak]
+            vte,
+            move |im_context| {
+                // Input method popup should appear next to the cursor
+                let (col, row) = vte.cursor_position();
+                let top_row = vte.vadjustment().map(|adj| adj.value()).unwrap_or(0.0);
+                let (width, height) = (vte.char_width() as i32, vte.char_height() as i32);
+                let x = col as i32 * width;
+                let y = (row as i32 - top_row as i32) * height;
+                im_context.set_cursor_location(&Rectangle::new(x, y, width, height));
+            }
+        ));
+        im_context.connect_preedit_changed(glib::clone!(
+            #[weak]
+            terminal,
+            move |im_context| {
+                let (preedit, _, _) = im_context.preedit_string();
+                terminal.set_preedit(&preedit);
+            }
+        ));
+
+        // Only part of the Tmux history is captured initially, the rest is loaded
+        // once the user scrolls to the top
+        if let Some(adjustment) = vte.vadjustment() {
+            adjustment.connect_value_changed(glib::clone!(
+                #[weak]
+                terminal,
+                #[weak]
+                window,
+                move |adjustment| {
+                    if adjustment.value() > adjustment.lower() {
+                        return;
+                    }
+                    if terminal.start_history_reload() {
+                        window.load_pane_history(pane_id, None);
+                    }
+                }
+            ));
+        }
+
         let eventctl = EventControllerKey::new();
+        eventctl.set_im_context(Some(&im_context));
         eventctl.connect_key_pressed(glib::clone!(
             #[weak]
             vte,
@@ -89,7 +175,7 @@ impl TmuxTerminal {
             window,
             #[upgrade_or]
             Propagation::Proceed,
-            move |eventctl, keyval, key, state| {
+            move |eventctl, keyval, _key, state| {
                 if let Some(event) = eventctl.current_event() {
                     // Check if pressed keys match a keybinding
                     if let Some(action) = app.handle_keyboard_event(event) {
@@ -97,7 +183,9 @@ impl TmuxTerminal {
                         return Propagation::Stop;
                     }
                     // Normal button press is handled separately for Tmux
-                    window.tmux_keypress(pane_id, key, keyval, state)
+                    if window.tmux_keypress(pane_id, keyval, state) {
+                        return Propagation::Stop;
+                    }
                 }
                 Propagation::Proceed
             }
@@ -146,6 +234,51 @@ impl TmuxTerminal {
         self.imp().id.get()
     }
 
+    pub fn set_pane_property(&self, property: PaneProperty, value: String) {
+        let imp = self.imp();
+        let field = match property {
+            PaneProperty::CurrentPath => &imp.current_path,
+            PaneProperty::CurrentCommand => &imp.current_command,
+            PaneProperty::Title => &imp.title,
+        };
+        field.replace(value);
+    }
+
+    /// Working directory of the pane, None until Tmux reports it
+    pub fn current_path(&self) -> Option<String> {
+        let path = self.imp().current_path.borrow();
+        if path.is_empty() {
+            None
+        } else {
+            Some(path.clone())
+        }
+    }
+
+    pub fn current_command(&self) -> String {
+        self.imp().current_command.borrow().clone()
+    }
+
+    /// Describes what is running in the pane, e.g. "vim in /home/user"
+    pub fn description(&self) -> String {
+        let imp = self.imp();
+        let title = imp.title.borrow();
+        let command = imp.current_command.borrow();
+        let path = imp.current_path.borrow();
+
+        let running = match (command.is_empty(), path.is_empty()) {
+            (false, false) => format!("{} in {}", command, path),
+            (false, true) => command.clone(),
+            (true, false) => path.clone(),
+            (true, true) => String::new(),
+        };
+
+        [title.as_str(), running.as_str()]
+            .into_iter()
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
     pub fn update_config(&self, config: &TerminalConfig) {
         let color_scheme = ColorScheme::new(config);
         let vte = borrow_clone(&self.imp().vte);
@@ -163,17 +296,159 @@ impl TmuxTerminal {
     pub fn feed_output(&self, output: Vec<u8>, initial: bool) {
         let imp = self.imp();
 
-        if initial == false && imp.is_synced() == false {
-            // Regular output, but we are NOT yet synced!
-            return;
+        if !initial {
+            // Paused pane is redrawn once it catches up
+            if imp.paused.get() {
+                return;
+            }
+            // Regular output, but we are NOT yet synced! It is queued until the
+            // initial capture has been fed.
+            //
+            // Dropping output past the limit is safe: an unsynced Terminal always
+            // has a capture on the way, and Tmux never sends output in the middle
+            // of the capture block. So every byte queued here was received before
+            // the capture and is cleared by `initial_output_started()`, the capture
+            // showing it instead.
+            if !imp.is_synced() {
+                let mut pending_output = imp.pending_output.borrow_mut();
+                if pending_output.len() + output.len() <= PENDING_OUTPUT_LIMIT {
+                    pending_output.extend(output);
+                } else {
+                    debug!(
+                        "Dropping {} bytes of output for unsynced pane {}, capture replaces them",
+                        output.len(),
+                        self.id()
+                    );
+                }
+                return;
+            }
         }
 
         let vte = borrow_clone(&imp.vte);
         vte.feed(&output);
     }
 
+    /// Pane is captured again, as if the Terminal was just created. Output is
+    /// queued until then.
+    pub fn resync(&self) {
+        let imp = self.imp();
+        imp.set_unsynced();
+        imp.pending_output.borrow_mut().clear();
+
+        let vte = borrow_clone(&imp.vte);
+        vte.feed(b"\x1b[H\x1b[2J\x1b[3J");
+    }
+
+    /// Output queued so far is already part of the capture we are about to receive
+    pub fn initial_output_started(&self) {
+        self.imp().pending_output.borrow_mut().clear();
+    }
+
     pub fn initial_output_finished(&self) {
-        self.imp().set_synced();
+        let imp = self.imp();
+        imp.set_synced();
+
+        // Replay output which was received after the capture
+        let pending_output = imp.pending_output.take();
+        if !pending_output.is_empty() {
+            let vte = borrow_clone(&imp.vte);
+            vte.feed(&pending_output);
+        }
+    }
+
+    /// History size reported along with the initial capture
+    pub fn set_history_size(&self, size: u32) {
+        self.imp().history.borrow_mut().reset(size);
+    }
+
+    /// Returns false if there is no more history to load (or it is already being
+    /// loaded)
+    fn start_history_reload(&self) -> bool {
+        let imp = self.imp();
+        if !imp.is_synced() || imp.paused.get() || imp.history_reload.get().is_some() {
+            return false;
+        }
+
+        // There is no point in loading more history than VTE is able to keep
+        let vte = borrow_clone(&imp.vte);
+        let max_lines = u32::try_from(vte.scrollback_lines()).unwrap_or(u32::MAX);
+        let Some(adjustment) = vte.vadjustment() else {
+            return false;
+        };
+        if !imp.history.borrow_mut().start_reload(max_lines) {
+            return false;
+        }
+
+        imp.history_reload
+            .replace(Some(adjustment.upper() - adjustment.value()));
+        true
+    }
+
+    /// Returns the range of history to capture, once it is known
+    pub fn history_captured(&self, capture: HistoryCapture) -> Option<HistoryRange> {
+        self.imp().history.borrow_mut().captured(capture)
+    }
+
+    /// Fills the Terminal with the loaded history and keeps the view on the same
+    /// lines it showed before. Returns the range to capture again if output moved
+    /// the lines in the meantime.
+    pub fn history_loaded(&self) -> Option<HistoryRange> {
+        let imp = self.imp();
+        let vte = borrow_clone(&imp.vte);
+
+        let loaded = imp.history.borrow_mut().loaded();
+        match loaded {
+            HistoryLoaded::Retry(range) => return Some(range),
+            HistoryLoaded::Output(output) => vte.feed(&output),
+            HistoryLoaded::Failed => {}
+        }
+
+        if let (Some(distance), Some(adjustment)) = (imp.history_reload.take(), vte.vadjustment()) {
+            adjustment.set_value(adjustment.upper() - distance);
+        }
+        None
+    }
+
+    /// While paused, Tmux does not send us any output, so we show a "catching up"
+    /// status until the screen is redrawn
+    pub fn set_paused(&self, paused: bool) {
+        let imp = self.imp();
+        imp.paused.replace(paused);
+
+        let status = borrow_clone(&imp.status);
+        if paused {
+            status.set_label("Catching up...");
+        }
+        status.set_visible(paused);
+    }
+
+    /// Shows text which is being composed using an input method
+    pub fn set_preedit(&self, preedit: &str) {
+        let imp = self.imp();
+        let status = borrow_clone(&imp.status);
+
+        if preedit.is_empty() {
+            // Restore the status we might have been showing before
+            self.set_paused(imp.paused.get());
+        } else {
+            status.set_label(preedit);
+            status.set_visible(true);
+        }
+    }
+
+    /// Shows how much of a large paste has been sent, None once it is done
+    pub fn set_paste_progress(&self, progress: Option<f64>) {
+        let imp = self.imp();
+        let status = borrow_clone(&imp.status);
+
+        match progress {
+            Some(progress) => {
+                status.set_label(&format!("Pasting... {:.0}%", progress * 100.0));
+                status.set_visible(true);
+            }
+            // Restore the status we might have been showing before
+            None => self.set_paused(imp.paused.get()),
+        }
     }
 
     pub fn scroll_view(&self, empty_lines: usize) {
@@ -183,9 +458,7 @@ impl TmuxTerminal {
 
         let mut output = Vec::with_capacity(empty_lines + 16);
         // Scroll down 'empty_lines' lines
-        for _ in 0..empty_lines {
-            output.push(b'\n');
-        }
+        output.resize(empty_lines, b'\n');
         // Scroll back up '# = empty_lines' lines using ESC[#A
         output.push(b'\x1b');
         output.push(b'[');
@@ -210,12 +483,22 @@ impl TmuxTerminal {
     }
 
     pub fn clear_scrollback(&self) {
+        let imp = self.imp();
+        imp.history.replace(PaneHistory::default());
+
         let clear_scrollback = [b'\x1b', b'[', b'3', b'J'];
-        let vte = borrow_clone(&self.imp().vte);
+        let vte = borrow_clone(&imp.vte);
         vte.feed(&clear_scrollback);
     }
 }
 
+/// Other data VTE sends (e.g. replies to terminal queries) is already handled by
+/// Tmux itself, so only mouse reports are forwarded
+#[inline]
+fn is_mouse_report(text: &str) -> bool {
+    text.starts_with("\x1b[M") || text.starts_with("\x1b[<")
+}
+
 #[inline]
 fn handle_keyboard_event(
     action: KeyboardAction,
@@ -234,6 +517,15 @@ fn handle_keyboard_event(
         KeyboardAction::TabRename => {
             top_level.open_rename_modal();
         }
+        KeyboardAction::OpenEditorCwd => {
+            window.open_editor_cwd(pane_id);
+        }
+        KeyboardAction::TabLayoutSave => {
+            window.save_tab_layout(top_level);
+        }
+        KeyboardAction::TabLayoutRestore => {
+            window.restore_tab_layout(top_level);
+        }
         _ => {
             window.tmux_handle_keybinding(action, pane_id);
         }
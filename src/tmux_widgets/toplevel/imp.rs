@@ -1,8 +1,10 @@
 use std::cell::{Cell, RefCell};
 
+use enumflags2::BitFlags;
 use gtk4::Widget;
 use libadwaita::{glib, subclass::prelude::*, TabView};
 
+use crate::tmux_api::LayoutFlags;
 use crate::tmux_widgets::{container::TmuxContainer, terminal::TmuxTerminal, IvyTmuxWindow};
 
 use super::layout::TopLevelLayout;
@@ -19,12 +21,21 @@ pub struct Zoomed {
 #[derive(Default)]
 pub struct TopLevelPriv {
     pub tab_id: Cell<u32>,
+    /// Index of the Tmux window, which determines the Tab order
+    pub index: Cell<u32>,
     pub window: RefCell<Option<IvyTmuxWindow>>,
     pub tab_view: RefCell<Option<TabView>>,
     // TODO: Replace this with SortedVec
     pub terminals: RefCell<Vec<TmuxTerminal>>,
     pub zoomed: RefCell<Option<Zoomed>>,
     pub focused_terminal: Cell<u32>,
+    /// Name of the Tmux window
+    pub name: RefCell<String>,
+    pub flags: Cell<BitFlags<LayoutFlags>>,
+    /// Input is mirrored to all panes (`synchronize-panes`)
+    pub synchronized: Cell<bool>,
+    /// Last `window_layout` received from Tmux
+    pub window_layout: RefCell<String>,
 }
 
 // The central trait for subclassing a GObject
@@ -2,17 +2,21 @@ mod imp;
 mod layout;
 mod tmux;
 
+use enumflags2::BitFlags;
 use glib::{subclass::types::ObjectSubclassIsExt, Object};
-use gtk4::Widget;
+use gtk4::{gio, Widget};
 use libadwaita::{glib, prelude::*, TabView};
 use log::debug;
 
-use crate::{helpers::borrow_clone, modals::spawn_rename_modal};
+use crate::{helpers::borrow_clone, modals::spawn_rename_modal, tmux_api::LayoutFlags};
 
 use self::imp::Zoomed;
 
 use super::{container::TmuxContainer, terminal::TmuxTerminal, IvyTmuxWindow};
 
+/// Marks Terminals of a Tab with `synchronize-panes` enabled, see style.css
+const SYNCHRONIZED_CSS_CLASS: &str = "synchronized_terminal";
+
 glib::wrapper! {
     pub struct TmuxTopLevel(ObjectSubclass<imp::TopLevelPriv>)
         @extends libadwaita::Bin, gtk4::Widget,
@@ -35,6 +39,14 @@ impl TmuxTopLevel {
         self.imp().tab_id.get()
     }
 
+    pub fn index(&self) -> u32 {
+        self.imp().index.get()
+    }
+
+    pub fn set_index(&self, index: u32) {
+        self.imp().index.replace(index);
+    }
+
     pub fn zoom(&self, term_id: u32, terminal: TmuxTerminal) -> Zoomed {
         // Remove Terminal from its parent Container
         let container: TmuxContainer = terminal.parent().unwrap().downcast().unwrap();
@@ -48,7 +60,7 @@ impl TmuxTopLevel {
 
         Zoomed {
             term_id,
-            terminal: terminal,
+            terminal,
             root_container,
             terminal_container: container,
             previous_sibling,
@@ -68,6 +80,10 @@ impl TmuxTopLevel {
         let pane_id = terminal.id();
         let imp = self.imp();
 
+        if imp.synchronized.get() {
+            terminal.add_css_class(SYNCHRONIZED_CSS_CLASS);
+        }
+
         let mut terminals_vec = imp.terminals.borrow_mut();
         terminals_vec.push(terminal.clone());
 
@@ -84,6 +100,7 @@ impl TmuxTopLevel {
             // Focused Terminal changed, we should notify Tmux of this
             let window = borrow_clone(&imp.window);
             window.gtk_terminal_focus_changed(term_id);
+            self.update_page();
         }
     }
 
@@ -99,6 +116,7 @@ impl TmuxTopLevel {
                 break;
             }
         }
+        self.update_page();
     }
 
     pub fn get_cols_rows(&self) -> (i32, i32) {
@@ -183,11 +201,99 @@ impl TmuxTopLevel {
     }
 
     pub fn tab_renamed(&self, new_name: &str) {
+        self.imp().name.replace(new_name.to_string());
+        self.update_page();
+    }
+
+    /// Shows Tmux window alerts (bell, activity and silence) on the Tab
+    pub fn set_flags(&self, flags: BitFlags<LayoutFlags>) {
         let imp = self.imp();
+        imp.flags.replace(flags);
+
         let tab_view = borrow_clone(&imp.tab_view);
+        // TODO: Just store the Page directly instead of tab_view
+        let page = tab_view.page(self);
+
+        // Selected Tab does not need to draw attention to itself
+        let icon_name = if page.is_selected() {
+            None
+        } else if flags.contains(LayoutFlags::Bell) {
+            Some("alarm-symbolic")
+        } else if flags.contains(LayoutFlags::Activity) {
+            Some("emblem-important-symbolic")
+        } else if flags.contains(LayoutFlags::Silence) {
+            Some("audio-volume-muted-symbolic")
+        } else {
+            None
+        };
+
+        match icon_name {
+            Some(icon_name) => {
+                page.set_indicator_icon(Some(&gio::ThemedIcon::new(icon_name)));
+                page.set_needs_attention(true);
+            }
+            None => {
+                page.set_indicator_icon(None::<&gio::Icon>);
+                page.set_needs_attention(false);
+            }
+        }
+    }
+
+    /// Marks all Terminals while input is mirrored to them
+    pub fn set_synchronized(&self, synchronized: bool) {
+        let imp = self.imp();
+        imp.synchronized.replace(synchronized);
+
+        for terminal in imp.terminals.borrow().iter() {
+            if synchronized {
+                terminal.add_css_class(SYNCHRONIZED_CSS_CLASS);
+            } else {
+                terminal.remove_css_class(SYNCHRONIZED_CSS_CLASS);
+            }
+        }
+    }
+
+    pub fn window_layout(&self) -> String {
+        self.imp().window_layout.borrow().clone()
+    }
+
+    /// Tab which was focused before the current one
+    pub fn is_last(&self) -> bool {
+        self.imp().flags.get().contains(LayoutFlags::IsLast)
+    }
+
+    pub fn pane_property_changed(&self, term_id: u32) {
+        if self.imp().focused_terminal.get() == term_id {
+            self.update_page();
+        }
+    }
+
+    /// Tab tooltip describes the focused Terminal, whose command is also used as
+    /// the title if the Tmux window has no name
+    fn update_page(&self) {
+        let imp = self.imp();
+        let focused_terminal = imp.focused_terminal.get();
+        let terminals = imp.terminals.borrow();
+        let terminal = terminals
+            .iter()
+            .find(|terminal| terminal.id() == focused_terminal);
 
         // TODO: Just store the Page directly instead of tab_view
+        let tab_view = borrow_clone(&imp.tab_view);
         let page = tab_view.page(self);
-        page.set_title(new_name);
+
+        let name = imp.name.borrow();
+        if !name.is_empty() {
+            page.set_title(&name);
+        } else if let Some(terminal) = terminal {
+            let command = terminal.current_command();
+            if !command.is_empty() {
+                page.set_title(&command);
+            }
+        }
+
+        if let Some(terminal) = terminal {
+            page.set_tooltip(&glib::markup_escape_text(&terminal.description()));
+        }
     }
 }
@@ -34,7 +34,7 @@ fn print_tab_debug(nested: u32) {
 }
 
 impl TmuxTopLevel {
-    fn close_removed_terminals(&self, window: &IvyTmuxWindow, layout: &Vec<TmuxPane>) {
+    fn close_removed_terminals(&self, window: &IvyTmuxWindow, layout: &[TmuxPane]) {
         let mut registered_terminals = self.imp().terminals.borrow_mut();
         let original_len = registered_terminals.len();
 
@@ -45,14 +45,11 @@ impl TmuxTopLevel {
             let mut still_exists = false;
             // Check if our registered terminal has NOT been closed
             for pane in layout.iter() {
-                match pane {
-                    TmuxPane::Terminal(pane_id, _) => {
-                        if term_id == *pane_id {
-                            still_exists = true;
-                            break;
-                        }
+                if let TmuxPane::Terminal(pane_id, _) = pane {
+                    if term_id == *pane_id {
+                        still_exists = true;
+                        break;
                     }
-                    _ => {}
                 }
             }
             if still_exists {
@@ -94,7 +91,7 @@ impl TmuxTopLevel {
     fn handle_zoomed_terminal(
         &self,
         window: &IvyTmuxWindow,
-        visible_layout: &Vec<TmuxPane>,
+        visible_layout: &[TmuxPane],
     ) -> Zoomed {
         // Check that visible_layout is not empty
         let pane = match visible_layout.first() {
@@ -145,6 +142,7 @@ impl TmuxTopLevel {
         if let Some(name) = layout_sync.name {
             self.tab_renamed(&name);
         }
+        self.imp().window_layout.replace(layout_sync.window_layout);
 
         // First Unzoom (we AWLAYS unzoom to handle corner cases better)
         let imp = self.imp();
@@ -370,7 +368,7 @@ fn container_callback(
     next_sibling: &Option<Widget>,
     nested: u32,
 ) -> TmuxContainer {
-    let position = calculate_position(&bounds, parent);
+    let position = calculate_position(bounds, parent);
 
     // Check if next_sibling even exists
     if let Some(next_pane) = next_sibling {
@@ -417,7 +415,7 @@ fn container_callback(
         )
     }
 
-    let container = TmuxContainer::new(&orientation);
+    let container = TmuxContainer::new(orientation);
     prepend_pane(window, &parent.c, &container, next_sibling, &position);
 
     container
@@ -436,7 +434,7 @@ fn terminal_callback(
 ) -> TmuxTerminal {
     // We know Terminal with given pane_id should be exactly *here* (as in before/exactly next_sibling)
     // next_sibling is always either Terminal or Container
-    let position = calculate_position(&bounds, parent);
+    let position = calculate_position(bounds, parent);
 
     // Check if a terminal with the given pane_id already exists
     if let Some(existing) = window.get_terminal_by_id(pane_id) {
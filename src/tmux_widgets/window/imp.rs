@@ -1,29 +1,52 @@
 use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::rc::Rc;
 
 use glib::Propagation;
+use gtk4::{Label, ListBox};
 use libadwaita::subclass::prelude::*;
-use libadwaita::{glib, ApplicationWindow, TabView};
+use libadwaita::{glib, prelude::*, ApplicationWindow, TabView, ToastOverlay};
 
+use crate::application::IvyApplication;
+use crate::config::TmuxCloseAction;
 use crate::helpers::SortedVec;
-use crate::tmux_api::TmuxAPI;
+use crate::modals::spawn_tmux_exit_modal;
+use crate::tmux_api::{TmuxAPI, TmuxSession};
 use crate::tmux_widgets::terminal::TmuxTerminal;
 use crate::tmux_widgets::toplevel::TmuxTopLevel;
 
-use super::tmux::TmuxInitState;
+use super::tmux::{PendingPaste, TmuxInitState};
 
 // Object holding the state
 #[derive(Default)]
 pub struct IvyWindowPriv {
     pub tmux: RefCell<Option<Rc<TmuxAPI>>>,
     pub tab_view: RefCell<Option<TabView>>,
+    pub toast_overlay: RefCell<Option<ToastOverlay>>,
+    pub session_list: RefCell<Option<ListBox>>,
+    /// Sessions shown in the session menu, in the same order as the rows
+    pub sessions: RefCell<Vec<TmuxSession>>,
     // TODO: Use SortedVec
     pub tabs: RefCell<Vec<TmuxTopLevel>>,
     pub terminals: RefCell<SortedVec<TmuxTerminal>>,
     pub char_size: Cell<(i32, i32)>,
     pub focused_tab: Cell<u32>,
-    pub session: Cell<Option<(u32, String)>>,
+    pub session: RefCell<Option<(u32, String)>>,
     pub init_layout_finished: Cell<TmuxInitState>,
+    /// Pastes which are being copied to Tmux, keyed by pane ID
+    pub pastes: RefCell<HashMap<u32, PendingPaste>>,
+    /// Output of panes whose Terminal has not been created yet. None once there
+    /// was too much of it, and the pane has to be captured instead.
+    pub pending_output: RefCell<HashMap<u32, Option<Vec<u8>>>>,
+    /// Set while we reorder Tabs to match Tmux, so it is not sent back to Tmux
+    pub reordering_tabs: Cell<bool>,
+    /// Window may close without asking what happens to the Tmux session
+    pub close_allowed: Cell<bool>,
+    /// SSH target, kept for reconnecting
+    pub ssh_target: RefCell<Option<String>>,
+    /// Set while the SSH connection is being reestablished
+    pub reconnecting: Cell<bool>,
+    pub disconnected_banner: RefCell<Option<Label>>,
 }
 
 // The central trait for subclassing a GObject
@@ -43,6 +66,9 @@ impl ObjectImpl for IvyWindowPriv {
 
         // Close all remaining pages
         self.tab_view.take();
+        self.toast_overlay.take();
+        self.session_list.take();
+        self.disconnected_banner.take();
     }
 }
 
@@ -52,6 +78,37 @@ impl WidgetImpl for IvyWindowPriv {}
 // Trait shared by all windows
 impl WindowImpl for IvyWindowPriv {
     fn close_request(&self) -> Propagation {
+        // User decides whether the Tmux session keeps running after we close
+        if !self.close_allowed.get() && self.tmux.borrow().is_some() {
+            let window = self.obj();
+            let app: IvyApplication = window.application().unwrap().downcast().unwrap();
+
+            match app.get_tmux_close_action() {
+                TmuxCloseAction::Ask => {
+                    let end_session = glib::closure_local!(
+                        #[weak]
+                        window,
+                        #[weak]
+                        app,
+                        move |kill: bool, remember: bool| {
+                            if remember {
+                                app.set_tmux_close_action(if kill {
+                                    TmuxCloseAction::Kill
+                                } else {
+                                    TmuxCloseAction::Detach
+                                });
+                            }
+                            window.end_session(kill);
+                        }
+                    );
+                    spawn_tmux_exit_modal(window.upcast_ref(), end_session);
+                    return Propagation::Stop;
+                }
+                TmuxCloseAction::Detach => window.leave_session(false),
+                TmuxCloseAction::Kill => window.leave_session(true),
+            }
+        }
+
         // Clear Tabs and Terminals
         self.tmux.take();
         self.terminals.borrow_mut().clear();
@@ -77,8 +134,18 @@ impl ApplicationWindowImpl for IvyWindowPriv {}
 impl AdwApplicationWindowImpl for IvyWindowPriv {}
 
 impl IvyWindowPriv {
-    pub fn initialize(&self, tab_view: &TabView) {
+    pub fn initialize(
+        &self,
+        tab_view: &TabView,
+        toast_overlay: &ToastOverlay,
+        session_list: &ListBox,
+        disconnected_banner: &Label,
+    ) {
         let mut binding = self.tab_view.borrow_mut();
         binding.replace(tab_view.clone());
+        self.toast_overlay.replace(Some(toast_overlay.clone()));
+        self.session_list.replace(Some(session_list.clone()));
+        self.disconnected_banner
+            .replace(Some(disconnected_banner.clone()));
     }
 }
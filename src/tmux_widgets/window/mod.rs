@@ -1,22 +1,25 @@
 mod imp;
 mod tmux;
 
-use std::rc::Rc;
+use std::{rc::Rc, time::Duration};
 
 use glib::{subclass::types::ObjectSubclassIsExt, Object, Propagation};
-use gtk4::{Align, Box, Button, Orientation, PackType, WindowControls, WindowHandle};
-use libadwaita::{gio, glib, prelude::*, ApplicationWindow, TabBar, TabView};
+use gtk4::{
+    Align, Box, Button, Label, ListBox, MenuButton, Orientation, PackType, Popover, SelectionMode,
+    WindowControls, WindowHandle,
+};
+use libadwaita::{gio, glib, prelude::*, ApplicationWindow, TabBar, TabView, Toast, ToastOverlay};
 use log::debug;
 use tmux::TmuxInitState;
 
 use crate::{
     application::IvyApplication,
     config::{TerminalConfig, APPLICATION_TITLE, INITIAL_HEIGHT, INITIAL_WIDTH},
-    helpers::borrow_clone,
+    helpers::{borrow_clone, open_editor, TmuxError},
     keyboard::KeyboardAction,
-    modals::spawn_new_tmux_modal,
+    modals::{new_ssh_prompter, spawn_new_tmux_modal},
     ssh::{new_session, SSHData},
-    tmux_api::TmuxAPI,
+    tmux_api::{AttachMode, TmuxAPI},
 };
 
 use super::{terminal::TmuxTerminal, toplevel::TmuxTopLevel};
@@ -24,13 +27,19 @@ use super::{terminal::TmuxTerminal, toplevel::TmuxTopLevel};
 #[macro_export]
 macro_rules! close_on_error {
     ( $e:expr, $window:ident ) => {
-        if let Err(_) = $e {
-            $window.close();
+        if let Err(err) = $e {
+            $window.tmux_failed(err);
             return;
         }
     };
 }
 
+/// How long (in seconds) error messages are shown
+const TOAST_TIMEOUT: u32 = 5;
+/// Delay before the first reconnect attempt, doubled after each failed one
+const RECONNECT_DELAY_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(30);
+
 #[inline]
 fn get_tmux_ref(window: &IvyTmuxWindow) -> Option<Rc<TmuxAPI>> {
     window.imp().tmux.borrow().clone()
@@ -43,7 +52,12 @@ glib::wrapper! {
 }
 
 impl IvyTmuxWindow {
-    pub fn new(app: &IvyApplication, tmux_session: &str, ssh_host: Option<(&str, &str)>) -> Self {
+    pub fn new(
+        app: &IvyApplication,
+        tmux_session: &str,
+        attach_mode: AttachMode,
+        ssh_target: Option<&str>,
+    ) -> Self {
         let window: Self = Object::builder().build();
         window.set_application(Some(app));
         window.set_title(Some(APPLICATION_TITLE));
@@ -56,18 +70,44 @@ impl IvyTmuxWindow {
 
         // View stack holds all panes
         let tab_view = TabView::new();
-        window.imp().initialize(&tab_view);
-
-        // Close Window automatically, when all pages (Tabs) have been closed
+        // Toasts are used to report Tmux errors
+        let toast_overlay = ToastOverlay::new();
+        toast_overlay.set_child(Some(&tab_view));
+        // Session menu lists sessions of the Tmux server
+        let session_list = ListBox::builder()
+            .selection_mode(SelectionMode::None)
+            .build();
+        // Banner is shown above the Tabs while SSH is reconnecting
+        let disconnected_banner = Label::builder()
+            .css_classes(["disconnected_banner"])
+            .visible(false)
+            .build();
+        window.imp().initialize(
+            &tab_view,
+            &toast_overlay,
+            &session_list,
+            &disconnected_banner,
+        );
+
+        // Close Window automatically, when all pages (Tabs) have been closed. While
+        // switching sessions, all pages are closed on purpose.
         tab_view.connect_n_pages_notify(glib::clone!(
             #[weak]
             window,
             move |tab_view| {
-                if tab_view.n_pages() < 1 {
-                    window.close();
+                if tab_view.n_pages() < 1 && window.initial_layout_finished() {
+                    window.close_without_asking();
                 }
             }
         ));
+        // Tmux windows are reordered to match the Tabs
+        tab_view.connect_page_reordered(glib::clone!(
+            #[weak]
+            window,
+            move |_, page, position| {
+                window.gtk_tab_reordered(page, position);
+            }
+        ));
         // Automatically remove unregister Tabs/Terminals when their respective
         // page is closed
         tab_view.connect_close_page(glib::clone!(
@@ -106,6 +146,29 @@ impl IvyTmuxWindow {
             }
         ));
 
+        // Tmux session switch
+        let session_popover = Popover::builder().child(&session_list).build();
+        session_popover.connect_show(glib::clone!(
+            #[weak]
+            window,
+            move |_| {
+                window.refresh_session_menu();
+            }
+        ));
+        session_list.connect_row_activated(glib::clone!(
+            #[weak]
+            window,
+            #[weak]
+            session_popover,
+            move |_, row| {
+                session_popover.popdown();
+                window.switch_tmux_session(row.index());
+            }
+        ));
+        let session_button = MenuButton::builder()
+            .label("Sessions")
+            .popover(&session_popover)
+            .build();
         // Terminal settings
         let tmux_button = Button::with_label("Tmux");
         tmux_button.connect_clicked(glib::clone!(
@@ -126,6 +189,7 @@ impl IvyTmuxWindow {
         ));
         // HeaderBar end widgets
         let end_widgets = Box::new(Orientation::Horizontal, 3);
+        end_widgets.append(&session_button);
         end_widgets.append(&tmux_button);
         end_widgets.append(&settings_button);
 
@@ -158,26 +222,42 @@ impl IvyTmuxWindow {
             .build();
 
         window_box.append(&window_handle);
-        window_box.append(&tab_view);
+        window_box.append(&disconnected_banner);
+        window_box.append(&toast_overlay);
         window.set_content(Some(&window_box));
 
-        if let Some((ssh_target, ssh_password)) = ssh_host {
-            new_ssh_session(&window, tmux_session, ssh_target, ssh_password);
+        if let Some(ssh_target) = ssh_target {
+            new_ssh_session(&window, tmux_session, attach_mode, ssh_target);
         } else {
-            window.initialize_tmux(tmux_session, None);
+            window.initialize_tmux(tmux_session, attach_mode, None);
         }
 
         window
     }
 
     /// Called after both Tmux and SSH session are ready (if it exists)
-    fn initialize_tmux(&self, tmux_session: &str, ssh_data: Option<SSHData>) {
+    fn initialize_tmux(
+        &self,
+        tmux_session: &str,
+        attach_mode: AttachMode,
+        ssh_data: Option<SSHData>,
+    ) {
         // Initialize Tmux API
-        let tmux = TmuxAPI::new(tmux_session, ssh_data, self).unwrap();
+        let tmux = match TmuxAPI::new(tmux_session, attach_mode, ssh_data, self) {
+            Ok(tmux) => tmux,
+            Err(err) => {
+                eprintln!("Could not start Tmux: {}", err);
+                self.connection_lost();
+                return;
+            }
+        };
         self.imp().tmux.replace(Some(Rc::new(tmux)));
 
         // Get initial Tmux layout
         if let Some(tmux) = get_tmux_ref(self) {
+            close_on_error!(tmux.enable_flow_control(), self);
+            close_on_error!(tmux.subscribe_pane_properties(), self);
+            close_on_error!(tmux.subscribe_windows(), self);
             close_on_error!(tmux.get_initial_layout(), self);
         }
     }
@@ -199,6 +279,9 @@ impl IvyTmuxWindow {
             self,
             move |page| {
                 if page.is_selected() {
+                    // Tmux clears alerts of the selected window as well
+                    page.set_indicator_icon(None::<&gio::Icon>);
+                    page.set_needs_attention(false);
                     window.gtk_tab_focus_changed(id);
                 }
             }
@@ -221,15 +304,33 @@ impl IvyTmuxWindow {
     pub fn register_terminal(&self, pane_id: u32, terminal: &TmuxTerminal) {
         let imp = self.imp();
         let mut terminals = imp.terminals.borrow_mut();
-        terminals.insert(pane_id, &terminal);
+        terminals.insert(pane_id, terminal);
         debug!("Terminal with ID {} registered", pane_id);
 
         let char_size = terminal.get_char_width_height();
         imp.char_size.replace(char_size);
+        drop(terminals);
+
+        // Pane might have printed something before we knew about it
+        let pending_output = imp.pending_output.borrow_mut().remove(&pane_id);
+        match pending_output {
+            Some(Some(output)) => terminal.feed_output(output, false),
+            // Too much to keep, so we capture the pane instead. Before the initial
+            // layout is done, every pane is captured anyway.
+            Some(None) if self.initial_layout_finished() => {
+                terminal.resync();
+                if let Some(tmux) = get_tmux_ref(self) {
+                    close_on_error!(tmux.get_initial_output(pane_id), self);
+                }
+            }
+            _ => {}
+        }
     }
 
     pub fn unregister_terminal(&self, pane_id: u32) {
-        let mut terminals = self.imp().terminals.borrow_mut();
+        let imp = self.imp();
+        imp.pending_output.borrow_mut().remove(&pane_id);
+        let mut terminals = imp.terminals.borrow_mut();
         terminals.remove(pane_id);
         debug!("Terminal with ID {} unregistered", pane_id);
     }
@@ -264,11 +365,67 @@ impl IvyTmuxWindow {
 
     #[inline]
     pub fn tmux_handle_keybinding(&self, action: KeyboardAction, pane_id: u32) {
+        if action == KeyboardAction::PaneJoin {
+            self.join_pane_to_last_tab(pane_id);
+            return;
+        }
+
         if let Some(tmux) = get_tmux_ref(self) {
             close_on_error!(tmux.send_keybinding(action, pane_id), self);
         }
     }
 
+    /// Pane is moved to the Tab which was focused before the current one, same as
+    /// Tmux would with `join-pane -t !`
+    fn join_pane_to_last_tab(&self, pane_id: u32) {
+        let last_tab = self
+            .imp()
+            .tabs
+            .borrow()
+            .iter()
+            .find(|tab| tab.is_last())
+            .map(|tab| tab.tab_id());
+
+        match (last_tab, get_tmux_ref(self)) {
+            (Some(tab_id), Some(tmux)) => close_on_error!(tmux.join_pane(pane_id, tab_id), self),
+            (None, _) => self.show_toast("There is no previous tab to move the pane to"),
+            _ => {}
+        }
+    }
+
+    pub fn save_tab_layout(&self, top_level: &TmuxTopLevel) {
+        let app = self.application().unwrap();
+        let app: IvyApplication = app.downcast().unwrap();
+
+        app.save_layout(top_level.window_layout());
+        self.show_toast("Tab layout saved");
+    }
+
+    pub fn restore_tab_layout(&self, top_level: &TmuxTopLevel) {
+        let app = self.application().unwrap();
+        let app: IvyApplication = app.downcast().unwrap();
+
+        match (app.get_saved_layout(), get_tmux_ref(self)) {
+            (Some(layout), Some(tmux)) => {
+                close_on_error!(tmux.apply_layout(top_level.tab_id(), &layout), self)
+            }
+            (None, _) => self.show_toast("No tab layout has been saved yet"),
+            _ => {}
+        }
+    }
+
+    pub fn open_editor_cwd(&self, pane_id: u32) {
+        let path = self
+            .get_terminal_by_id(pane_id)
+            .and_then(|terminal| terminal.current_path());
+
+        match (path, get_tmux_ref(self)) {
+            (Some(path), Some(tmux)) => open_editor(&path, tmux.ssh_target()),
+            (None, _) => self.show_toast("Current directory of the pane is not known yet"),
+            _ => {}
+        }
+    }
+
     pub fn gtk_terminal_focus_changed(&self, term_id: u32) {
         if let Some(tmux) = get_tmux_ref(self) {
             close_on_error!(tmux.select_terminal(term_id), self);
@@ -287,6 +444,135 @@ impl IvyTmuxWindow {
         }
     }
 
+    /// Closes the Window without asking what should happen to the Tmux session,
+    /// for example because it has already ended
+    pub fn close_without_asking(&self) {
+        self.imp().close_allowed.replace(true);
+        self.close();
+    }
+
+    /// Detaches from (or kills) the Tmux session and closes the Window
+    pub fn end_session(&self, kill: bool) {
+        self.leave_session(kill);
+        self.close_without_asking();
+    }
+
+    pub fn leave_session(&self, kill: bool) {
+        if let Some(tmux) = get_tmux_ref(self) {
+            // Command is written before returning, and the Window is closing
+            // either way, so errors do not matter
+            let _ = if kill {
+                tmux.kill_session()
+            } else {
+                tmux.detach()
+            };
+        }
+    }
+
+    /// Reconnects if the SSH connection dropped, otherwise Tmux is gone for good
+    pub fn tmux_failed(&self, err: TmuxError) {
+        match err {
+            TmuxError::SshClosed => self.connection_lost(),
+            _ => self.close_without_asking(),
+        }
+    }
+
+    /// Keeps the Tabs visible, while retrying the SSH connection with backoff.
+    /// Closes the Window if the session can not be reattached.
+    pub fn connection_lost(&self) {
+        let imp = self.imp();
+        let session_name = imp.session.borrow().as_ref().map(|(_, name)| name.clone());
+        let ssh_target = imp.ssh_target.borrow().clone();
+        let (session_name, ssh_target) = match (session_name, ssh_target) {
+            (Some(session_name), Some(ssh_target)) => (session_name, ssh_target),
+            _ => {
+                self.close_without_asking();
+                return;
+            }
+        };
+        if imp.reconnecting.replace(true) {
+            return;
+        }
+
+        let tab_view = borrow_clone(&imp.tab_view);
+        tab_view.set_sensitive(false);
+
+        // We might be running within the receive future of TmuxAPI, which is
+        // aborted once TmuxAPI is dropped
+        let window = self.downgrade();
+        // Same prompter is used by all attempts, so the user is asked only once
+        let prompter = new_ssh_prompter(self);
+        glib::spawn_future_local(async move {
+            let mut delay = RECONNECT_DELAY_MIN;
+            let mut attempt = 1;
+
+            loop {
+                match window.upgrade() {
+                    Some(window) => {
+                        let imp = window.imp();
+                        imp.tmux.take();
+                        imp.pastes.borrow_mut().clear();
+                        window.set_disconnected_status(Some(&format!(
+                            "Connection to {} lost, reconnecting (attempt {})...",
+                            ssh_target, attempt
+                        )));
+                    }
+                    None => return,
+                }
+
+                glib::timeout_future(delay).await;
+                let target = ssh_target.clone();
+                prompter.start_attempt();
+                let thread_prompter = prompter.clone();
+                let ret = gio::spawn_blocking(move || new_session(&target, &thread_prompter)).await;
+
+                let window = match window.upgrade() {
+                    Some(window) => window,
+                    None => return,
+                };
+                if let Ok(Ok(ssh_data)) = ret {
+                    window.reconnected(&session_name, ssh_data);
+                    return;
+                }
+                // User does not want to authenticate again
+                if prompter.cancelled() {
+                    window.close_without_asking();
+                    return;
+                }
+
+                delay = (delay * 2).min(RECONNECT_DELAY_MAX);
+                attempt += 1;
+            }
+        });
+    }
+
+    /// Reattaches to the same session, which is then synced from scratch
+    fn reconnected(&self, session_name: &str, ssh_data: SSHData) {
+        let imp = self.imp();
+        self.reset_session();
+        imp.reconnecting.replace(false);
+        self.set_disconnected_status(None);
+        borrow_clone(&imp.tab_view).set_sensitive(true);
+
+        self.initialize_tmux(session_name, AttachMode::Attach, Some(ssh_data));
+        self.show_toast("Reconnected");
+    }
+
+    fn set_disconnected_status(&self, status: Option<&str>) {
+        let banner = borrow_clone(&self.imp().disconnected_banner);
+        banner.set_label(status.unwrap_or_default());
+        banner.set_visible(status.is_some());
+    }
+
+    pub fn show_toast(&self, message: &str) {
+        let toast_overlay = borrow_clone(&self.imp().toast_overlay);
+        let toast = Toast::builder()
+            .title(message)
+            .timeout(TOAST_TIMEOUT)
+            .build();
+        toast_overlay.add_toast(toast);
+    }
+
     pub fn clipboard_paste_event(&self, pane_id: u32) {
         let clipboard = self.primary_clipboard();
         let future = clipboard.read_text_future();
@@ -295,10 +581,8 @@ impl IvyTmuxWindow {
             #[weak(rename_to = window)]
             self,
             async move {
-                if let Ok(output) = future.await {
-                    if let Some(output) = output {
-                        window.send_clipboard(pane_id, output.as_str());
-                    }
+                if let Ok(Some(output)) = future.await {
+                    window.paste_text(pane_id, output.as_str());
                 }
             }
         ));
@@ -308,25 +592,25 @@ impl IvyTmuxWindow {
 fn new_ssh_session(
     window: &IvyTmuxWindow,
     tmux_session: &str,
+    attach_mode: AttachMode,
     ssh_target: &str,
-    ssh_password: &str,
 ) {
     let tmux_session = tmux_session.to_string();
     let ssh_target = ssh_target.to_string();
-    let ssh_password = ssh_password.to_string();
+    window.imp().ssh_target.replace(Some(ssh_target.clone()));
+    let prompter = new_ssh_prompter(window);
 
     glib::spawn_future_local(glib::clone!(
         #[weak]
         window,
         async move {
-            let ret =
-                match gio::spawn_blocking(move || new_session(&ssh_target, &ssh_password)).await {
-                    Ok(ret) => ret,
-                    Err(_) => {
-                        window.close();
-                        return;
-                    }
-                };
+            let ret = match gio::spawn_blocking(move || new_session(&ssh_target, &prompter)).await {
+                Ok(ret) => ret,
+                Err(_) => {
+                    window.close();
+                    return;
+                }
+            };
 
             let tuple = match ret {
                 Ok(ret) => ret,
@@ -336,7 +620,7 @@ fn new_ssh_session(
                 }
             };
 
-            window.initialize_tmux(&tmux_session, Some(tuple));
+            window.initialize_tmux(&tmux_session, attach_mode, Some(tuple));
         }
     ));
 }
@@ -3,39 +3,49 @@ use std::time::Duration;
 use glib::subclass::types::ObjectSubclassIsExt;
 use gtk4::{
     gdk::{Key, ModifierType},
-    Orientation,
+    Align, Label, ListBoxRow, Orientation,
 };
-use libadwaita::{glib, prelude::*};
+use libadwaita::{glib, prelude::*, TabPage};
 use log::debug;
 
 use crate::{
     close_on_error,
     helpers::borrow_clone,
-    keyboard::{keycode_to_arrow_key, Direction},
-    tmux_api::{LayoutFlags, LayoutSync, TmuxEvent},
+    keyboard::{keyval_to_tmux_key, Direction},
+    tmux_api::{HistoryRange, LayoutFlags, LayoutSync, TmuxEvent, TmuxSession},
     tmux_widgets::{
-        separator::TmuxSeparator, terminal::TmuxTerminal, toplevel::TmuxTopLevel,
+        separator::TmuxSeparator,
+        terminal::{TmuxTerminal, PENDING_OUTPUT_LIMIT},
+        toplevel::TmuxTopLevel,
         window::get_tmux_ref,
     },
+    unwrap_or_return,
 };
 
 use super::IvyTmuxWindow;
 
 const RESIZE_TIMEOUT: Duration = Duration::from_millis(5);
+/// Gives user a chance to read why the Tmux session ended before closing the window
+const EXIT_TIMEOUT: Duration = Duration::from_secs(3);
+/// Pasted text is copied to a Tmux buffer in chunks of this many bytes
+const PASTE_CHUNK_SIZE: usize = 4096;
+/// Progress is shown for pastes larger than this many bytes
+const PASTE_PROGRESS_SIZE: usize = 64 * 1024;
+
+/// Clipboard text which is being copied to a Tmux buffer, one chunk at a time
+pub struct PendingPaste {
+    text: String,
+    sent: usize,
+}
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Default)]
 pub enum TmuxInitState {
+    #[default]
     SyncingLayout,
     SyncingSize,
     Done,
 }
 
-impl Default for TmuxInitState {
-    fn default() -> Self {
-        TmuxInitState::SyncingLayout
-    }
-}
-
 // Tmux session initialization:
 // 1. TmuxWindow constructor calls tmux.get_initial_layout()
 // 2. We receive initial layout, which is used to construct the hierarchy
@@ -47,63 +57,119 @@ impl IvyTmuxWindow {
         self.imp().char_size.get()
     }
 
-    pub fn tmux_keypress(&self, pane_id: u32, keycode: u32, keyval: Key, state: ModifierType) {
+    /// Sends the key to Tmux, returning false if Tmux has no name for it
+    pub fn tmux_keypress(&self, pane_id: u32, keyval: Key, state: ModifierType) -> bool {
         let tmux = match get_tmux_ref(self) {
             Some(tmux) => tmux,
-            None => return,
+            None => return false,
         };
 
-        let mut prefix = String::new();
-        let mut shift_relevant = false;
-        if state.contains(ModifierType::ALT_MASK) {
-            prefix.push_str("M-");
-            shift_relevant = true;
+        let key = match keyval_to_tmux_key(keyval, state) {
+            Some(key) => key,
+            None => return false,
+        };
+        tmux.send_keypress(pane_id, key);
+        self.flush_tmux_keypresses();
+        true
+    }
 
-            // Hacky workaround for Alt+Backspace
-            if keycode == 22 {
-                close_on_error!(tmux.send_keypress(pane_id, '\x7f', prefix, None), self);
+    /// Keystrokes are sent right away, unless earlier ones have not been answered
+    /// yet. Those typed in the meantime are sent together once Tmux answers.
+    fn flush_tmux_keypresses(&self) {
+        if let Some(tmux) = get_tmux_ref(self) {
+            if tmux.keypresses_in_flight() {
                 return;
             }
+            close_on_error!(tmux.flush_keypresses(), self);
         }
-        if state.contains(ModifierType::CONTROL_MASK) {
-            prefix.push_str("C-");
-            shift_relevant = true;
+    }
+
+    pub fn load_pane_history(&self, pane_id: u32, range: Option<HistoryRange>) {
+        if let Some(tmux) = get_tmux_ref(self) {
+            close_on_error!(tmux.get_pane_history(pane_id, range), self);
         }
-        // Uppercase characters work without S-, so this case is only
-        // relevant when Ctrl/Alt is also pressed
-        if state.contains(ModifierType::SHIFT_MASK) && shift_relevant {
-            prefix.push_str("S-");
+    }
+
+    pub fn send_mouse_report(&self, pane_id: u32, report: &[u8]) {
+        if let Some(tmux) = get_tmux_ref(self) {
+            close_on_error!(tmux.send_mouse_report(pane_id, report), self);
         }
+    }
+
+    /// Copies text to a Tmux buffer and pastes it once all chunks have been sent
+    pub fn paste_text(&self, pane_id: u32, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+
+        let mut pastes = self.imp().pastes.borrow_mut();
+        if let Some(paste) = pastes.get_mut(&pane_id) {
+            // Previous paste is still running, we simply extend it
+            paste.text.push_str(text);
+            return;
+        }
+        let paste = PendingPaste {
+            text: text.to_string(),
+            sent: 0,
+        };
+        pastes.insert(pane_id, paste);
+        drop(pastes);
+
+        self.send_paste_chunk(pane_id);
+    }
+
+    /// Sends the next chunk of the pending paste, called again once Tmux has
+    /// received the previous one
+    fn send_paste_chunk(&self, pane_id: u32) {
+        let tmux = match get_tmux_ref(self) {
+            Some(tmux) => tmux,
+            None => return,
+        };
+        let imp = self.imp();
+        let terminal = imp.terminals.borrow().get(pane_id);
+
+        let mut pastes = imp.pastes.borrow_mut();
+        let paste = match pastes.get_mut(&pane_id) {
+            Some(paste) => paste,
+            None => return,
+        };
 
-        // TODO: All keys can be handled using keyval.name(), just exclude Ctrl, Shift, Alt, Tab, etc
-        // - if char
-        // - if Ctrl, Shift, Alt, Tab, etc
-        // - else
-        let mut result = Ok(());
-        if let Some(c) = keyval.to_unicode() {
-            result = tmux.send_keypress(pane_id, c, prefix, None);
-        } else if let Some(direction) = keycode_to_arrow_key(keycode) {
-            let direction = match direction {
-                crate::keyboard::Direction::Left => "Left",
-                crate::keyboard::Direction::Right => "Right",
-                crate::keyboard::Direction::Up => "Up",
-                crate::keyboard::Direction::Down => "Down",
-            };
-            result = tmux.send_keypress(pane_id, ' ', prefix, Some(direction));
-        } else if keycode >= 67 && keycode < 120 {
-            // This is a Function key
-            if let Some(name) = keyval.name() {
-                let name = name.as_str();
-                let name = name.replace('_', "");
-                result = tmux.send_function_key(pane_id, &name);
+        if paste.sent >= paste.text.len() {
+            // Buffer holds the whole text, we can paste it
+            pastes.remove(&pane_id);
+            drop(pastes);
+
+            if let Some(terminal) = terminal {
+                terminal.set_paste_progress(None);
+            }
+            close_on_error!(tmux.paste_buffer(pane_id), self);
+            return;
+        }
+
+        // Chunk can not end in the middle of a character
+        let mut end = (paste.sent + PASTE_CHUNK_SIZE).min(paste.text.len());
+        while !paste.text.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        if let Some(terminal) = terminal {
+            if paste.text.len() > PASTE_PROGRESS_SIZE {
+                let progress = paste.sent as f64 / paste.text.len() as f64;
+                terminal.set_paste_progress(Some(progress));
             }
         }
+
+        let append = paste.sent > 0;
+        let result = tmux.paste_chunk(pane_id, &paste.text[paste.sent..end], append);
+        paste.sent = end;
+        drop(pastes);
         close_on_error!(result, self);
     }
 
-    pub fn send_clipboard(&self, pane_id: u32, text: &str) {
+    pub fn send_text(&self, pane_id: u32, text: &str) {
         if let Some(tmux) = get_tmux_ref(self) {
-            close_on_error!(tmux.send_quoted_text(pane_id, text), self);
+            tmux.send_quoted_text(pane_id, text);
+            self.flush_tmux_keypresses();
         }
     }
 
@@ -151,6 +217,7 @@ impl IvyTmuxWindow {
     fn sync_tmux_layout(&self, layout_sync: LayoutSync) {
         let tab_id = layout_sync.tab_id;
         let flags = layout_sync.flags;
+        let index = layout_sync.index;
 
         let top_level = if let Some(top_level) = self.get_top_level(tab_id) {
             debug!("Reusing top Level {}", top_level.tab_id());
@@ -162,6 +229,10 @@ impl IvyTmuxWindow {
 
         // Sync Tab layout
         top_level.sync_tmux_layout(self, layout_sync);
+        top_level.set_flags(flags);
+        if let Some(index) = index {
+            self.set_tab_index(&top_level, index);
+        }
 
         // If the Tab is focused, we remember that here
         if flags.contains(LayoutFlags::HasFocus) {
@@ -169,6 +240,62 @@ impl IvyTmuxWindow {
         }
     }
 
+    /// Moves the Tab to the position matching its Tmux window index
+    fn set_tab_index(&self, top_level: &TmuxTopLevel, index: u32) {
+        let imp = self.imp();
+        top_level.set_index(index);
+
+        let position = imp
+            .tabs
+            .borrow()
+            .iter()
+            .filter(|tab| tab.index() < index)
+            .count();
+
+        let tab_view = borrow_clone(&imp.tab_view);
+        let page = tab_view.page(top_level);
+        imp.reordering_tabs.replace(true);
+        tab_view.reorder_page(&page, position as i32);
+        imp.reordering_tabs.replace(false);
+    }
+
+    /// User moved a Tab, so Tmux windows have to be reordered as well. Tmux then
+    /// reports the new indexes of all affected windows.
+    pub fn gtk_tab_reordered(&self, page: &TabPage, position: i32) {
+        let imp = self.imp();
+        if imp.reordering_tabs.get() || imp.init_layout_finished.get() != TmuxInitState::Done {
+            return;
+        }
+
+        let top_level: TmuxTopLevel = unwrap_or_return!(page.child().downcast().ok());
+        let tab_id = top_level.tab_id();
+        let position = position as usize;
+
+        // Tabs in the order Tmux knows them
+        let mut tabs = imp.tabs.borrow().clone();
+        tabs.sort_by_key(|tab| tab.index());
+        let old_position = unwrap_or_return!(tabs.iter().position(|tab| tab.eq(&top_level)));
+        if old_position == position {
+            return;
+        }
+
+        let tmux = unwrap_or_return!(get_tmux_ref(self));
+        let tab_view = borrow_clone(&imp.tab_view);
+        let result = if old_position.abs_diff(position) == 1 {
+            // Neighbouring Tabs simply swap places
+            tmux.swap_tabs(tab_id, tabs[position].tab_id())
+        } else if position > 0 {
+            let previous = tab_view.nth_page(position as i32 - 1).child();
+            let previous: TmuxTopLevel = unwrap_or_return!(previous.downcast().ok());
+            tmux.move_tab(tab_id, previous.tab_id(), true)
+        } else {
+            let next = tab_view.nth_page(1).child();
+            let next: TmuxTopLevel = unwrap_or_return!(next.downcast().ok());
+            tmux.move_tab(tab_id, next.tab_id(), false)
+        };
+        close_on_error!(result, self);
+    }
+
     pub fn rename_tmux_tab(&self, tab_id: u32, name: &str) {
         if let Some(tmux) = get_tmux_ref(self) {
             close_on_error!(tmux.rename_tab(tab_id, name.to_string()), self);
@@ -187,10 +314,67 @@ impl IvyTmuxWindow {
         // It receives Tmux events from separate thread and runs GTK functions
         match event {
             TmuxEvent::Output(pane_id, output, initial) => {
-                // Ignore Output events until initial output has been captured
                 let terminals = imp.terminals.borrow();
                 if let Some(pane) = terminals.get(pane_id) {
                     pane.feed_output(output, initial);
+                } else if !initial {
+                    // Terminal is created once we receive the new layout
+                    let mut pending_output = imp.pending_output.borrow_mut();
+                    let pending = pending_output.entry(pane_id).or_insert(Some(Vec::new()));
+                    if let Some(output_so_far) = pending {
+                        if output_so_far.len() + output.len() <= PENDING_OUTPUT_LIMIT {
+                            output_so_far.extend(output);
+                        } else {
+                            // Replaying part of it would leave the Terminal garbled
+                            pending.take();
+                        }
+                    }
+                }
+            }
+            TmuxEvent::InitialOutputStarted(pane_id) => {
+                if let Some(pane) = imp.terminals.borrow().get(pane_id) {
+                    pane.initial_output_started();
+                }
+            }
+            TmuxEvent::PanePaused(pane_id) => {
+                if let Some(pane) = imp.terminals.borrow().get(pane_id) {
+                    pane.set_paused(true);
+                }
+
+                if let Some(tmux) = get_tmux_ref(self) {
+                    close_on_error!(tmux.catch_up_pane(pane_id), self);
+                }
+            }
+            TmuxEvent::PaneCaughtUp(pane_id) => {
+                if let Some(pane) = imp.terminals.borrow().get(pane_id) {
+                    pane.set_paused(false);
+                }
+            }
+            TmuxEvent::PaneHistorySize(pane_id, size) => {
+                if let Some(pane) = imp.terminals.borrow().get(pane_id) {
+                    pane.set_history_size(size);
+                }
+            }
+            TmuxEvent::PaneHistoryCaptured(pane_id, capture) => {
+                let pane = imp.terminals.borrow().get(pane_id);
+                let range = pane.and_then(|pane| pane.history_captured(capture));
+                if let Some(range) = range {
+                    self.load_pane_history(pane_id, Some(range));
+                }
+            }
+            TmuxEvent::PaneHistoryLoaded(pane_id) => {
+                let pane = imp.terminals.borrow().get(pane_id);
+                let range = pane.and_then(|pane| pane.history_loaded());
+                if let Some(range) = range {
+                    self.load_pane_history(pane_id, Some(range));
+                }
+            }
+            TmuxEvent::PanePropertyChanged(pane_id, property, value) => {
+                if let Some(pane) = imp.terminals.borrow().get(pane_id) {
+                    pane.set_pane_property(property, value);
+                }
+                for top_level in imp.tabs.borrow().iter() {
+                    top_level.pane_property_changed(pane_id);
                 }
             }
             TmuxEvent::PaneFocusChanged(tab_id, term_id) => {
@@ -212,10 +396,31 @@ impl IvyTmuxWindow {
                     }
                 }
             }
+            TmuxEvent::TabAdded(tab_id) => {
+                // Tmux only gives us the ID of the new Tab, so we need to ask for its layout
+                if let Some(tmux) = get_tmux_ref(self) {
+                    close_on_error!(tmux.get_tab_layout(tab_id), self);
+                }
+            }
             TmuxEvent::TabNew(layout_sync) => {
                 debug!("\n---------- New tab ----------");
                 self.sync_tmux_layout(layout_sync);
             }
+            TmuxEvent::TabFlagsChanged(tab_id, flags) => {
+                if let Some(top_level) = self.get_top_level(tab_id) {
+                    top_level.set_flags(flags);
+                }
+            }
+            TmuxEvent::TabSynchronizeChanged(tab_id, synchronized) => {
+                if let Some(top_level) = self.get_top_level(tab_id) {
+                    top_level.set_synchronized(synchronized);
+                }
+            }
+            TmuxEvent::TabIndexChanged(tab_id, index) => {
+                if let Some(top_level) = self.get_top_level(tab_id) {
+                    self.set_tab_index(&top_level, index);
+                }
+            }
             TmuxEvent::TabClosed(tab_id) => {
                 if let Some(top_level) = self.get_top_level(tab_id) {
                     self.close_tab(&top_level);
@@ -267,18 +472,49 @@ impl IvyTmuxWindow {
                         // If initial output has not been captured yet, now is the time
                         let terminals = imp.terminals.borrow();
                         for sorted in terminals.iter() {
-                            if let Err(_) = tmux.get_initial_output(sorted.id) {
+                            if tmux.get_initial_output(sorted.id).is_err() {
                                 drop(terminals);
-                                self.close();
+                                self.close_without_asking();
                                 return;
                             }
                         }
                     }
                 }
             }
-            TmuxEvent::Exit => {
-                debug!("Received EXIT event, closing window!");
-                self.close();
+            TmuxEvent::PasteChunkSent(pane_id) => {
+                self.send_paste_chunk(pane_id);
+            }
+            TmuxEvent::KeypressSent => {
+                if let Some(tmux) = get_tmux_ref(self) {
+                    tmux.keypress_answered();
+                }
+                self.flush_tmux_keypresses();
+            }
+            TmuxEvent::PasteFailed(pane_id) => {
+                imp.pastes.borrow_mut().remove(&pane_id);
+                if let Some(terminal) = imp.terminals.borrow().get(pane_id) {
+                    terminal.set_paste_progress(None);
+                }
+            }
+            TmuxEvent::CommandFailed(message) => {
+                self.show_toast(&message);
+            }
+            TmuxEvent::Exit(reason) => {
+                debug!("Received EXIT event ({:?}), closing window!", reason);
+                let message = match reason {
+                    Some(reason) => format!("Tmux session ended: {}", reason),
+                    None => String::from("Tmux session ended"),
+                };
+                self.show_toast(&message);
+
+                glib::spawn_future_local(glib::clone!(
+                    #[weak(rename_to = window)]
+                    self,
+                    async move {
+                        glib::timeout_future(EXIT_TIMEOUT).await;
+                        window.close_without_asking();
+                    }
+                ));
             }
             TmuxEvent::ScrollOutput(pane_id, empty_lines) => {
                 let terminals = &imp.terminals;
@@ -287,19 +523,25 @@ impl IvyTmuxWindow {
                 }
             }
             TmuxEvent::SessionChanged(id, name) => {
-                let new = (id, name.clone());
-                let old = imp.session.replace(Some((id, name)));
-
-                // If session changes (after it was already initialized), then
-                // something went wrong
-                if let Some(old) = old {
-                    if old != new {
-                        println!("Session {} changed underneath us, closing Window", old.1);
-                        self.close();
+                let old = imp.session.replace(Some((id, name.clone())));
+
+                // If session changes (after it was already initialized), we need to
+                // sync the new session from scratch
+                if let Some((old_id, old_name)) = old {
+                    if old_id != id {
+                        debug!("Switched from session {} to {}", old_name, name);
+                        self.reset_session();
+                        return;
                     }
                 }
 
-                println!("Session {} with name {} initialized", new.0, new.1);
+                debug!("Session {} with name {} initialized", id, name);
+            }
+            TmuxEvent::SessionList(sessions) => {
+                self.update_session_menu(sessions);
+            }
+            TmuxEvent::Disconnected => {
+                self.connection_lost();
             }
             TmuxEvent::ScrollbackCleared(term_id) => {
                 let terminals = &imp.terminals;
@@ -310,6 +552,77 @@ impl IvyTmuxWindow {
         }
     }
 
+    /// Closes all Tabs of the previous session and syncs the current one, same as
+    /// when the Tmux session is first initialized
+    pub fn reset_session(&self) {
+        let imp = self.imp();
+        imp.init_layout_finished
+            .replace(TmuxInitState::SyncingLayout);
+
+        let tabs = imp.tabs.borrow().clone();
+        for top_level in tabs.iter() {
+            self.close_tab(top_level);
+        }
+        imp.pending_output.borrow_mut().clear();
+
+        if let Some(tmux) = get_tmux_ref(self) {
+            // Size of the new session has to be synced, even if our size is the same
+            tmux.reset_size();
+            close_on_error!(tmux.get_initial_layout(), self);
+        }
+    }
+
+    pub fn refresh_session_menu(&self) {
+        if let Some(tmux) = get_tmux_ref(self) {
+            close_on_error!(tmux.get_sessions(), self);
+        }
+    }
+
+    fn update_session_menu(&self, sessions: Vec<TmuxSession>) {
+        let imp = self.imp();
+        let session_list = borrow_clone(&imp.session_list);
+        while let Some(row) = session_list.row_at_index(0) {
+            session_list.remove(&row);
+        }
+
+        let current_session = imp.session.borrow().as_ref().map(|(id, _)| *id);
+        for session in sessions.iter() {
+            let is_current = current_session == Some(session.id);
+            let text = format!(
+                "{} ({} windows){}",
+                session.name,
+                session.windows,
+                if is_current { " - current" } else { "" }
+            );
+            let label = Label::builder()
+                .label(&text)
+                .halign(Align::Start)
+                .margin_top(5)
+                .margin_bottom(5)
+                .margin_start(5)
+                .margin_end(5)
+                .build();
+            let row = ListBoxRow::builder()
+                .child(&label)
+                .activatable(!is_current)
+                .build();
+            session_list.append(&row);
+        }
+
+        imp.sessions.replace(sessions);
+    }
+
+    pub fn switch_tmux_session(&self, index: i32) {
+        let session_id = match self.imp().sessions.borrow().get(index as usize) {
+            Some(session) => session.id,
+            None => return,
+        };
+
+        if let Some(tmux) = get_tmux_ref(self) {
+            close_on_error!(tmux.switch_session(session_id), self);
+        }
+    }
+
     pub fn initial_layout_finished(&self) -> bool {
         self.imp().init_layout_finished.get() == TmuxInitState::Done
     }
@@ -322,7 +635,7 @@ impl IvyTmuxWindow {
             (true, _) => Direction::Left,
             (false, _) => Direction::Right,
         };
-        let amount = amount.abs() as u32;
+        let amount = amount.unsigned_abs();
 
         if let Some(tmux) = get_tmux_ref(self) {
             // We need to find widget to the top/left of our separator